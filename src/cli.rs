@@ -1,4 +1,6 @@
-use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use crate::repository::deck::RepeatAlgorithm;
 
 #[derive(Debug, Parser)]
 #[command(name = "mem-yaml")]
@@ -11,11 +13,207 @@ pub struct Cli {
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     Init(DirArgs),
-    Start(DirArgs),
+    Start(StartArgs),
+    /// Train personalized FSRS weights from the review log
+    Optimize(DirArgs),
+    /// Switch the scheduling algorithm of a deck, converting the state of reviewed cards
+    MigrateAlgorithm(MigrateAlgorithmArgs),
+    /// Show card counts, workload and retention of a deck
+    Stats(StatsArgs),
+    /// Exclude cards from scheduling
+    Suspend(SelectArgs),
+    /// Bring suspended cards back into scheduling
+    Unsuspend(SelectArgs),
+    /// List the cards of a deck
+    List(ListArgs),
+    /// Validate the deck metadata and card files
+    Check(DirArgs),
+    /// Show the due and new cards of every deck in a collection
+    Decks(DirArgs),
+    /// Create a deck from cards of another tool
+    #[command(subcommand)]
+    Import(ImportCommands),
+    /// Write a deck in the format of another tool
+    #[command(subcommand)]
+    Export(ExportCommands),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportCommands {
+    /// Import an Anki .apkg or .colpkg package
+    Anki(AnkiImportArgs),
+    /// Add cards from a CSV or TSV file
+    Csv(CsvImportArgs),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExportCommands {
+    /// Export an Anki .apkg package
+    Anki(AnkiExportArgs),
+    /// Export cards to a CSV or TSV file
+    Csv(CsvExportArgs),
 }
 
 #[derive(Debug, Args)]
 pub struct DirArgs {
     #[arg(short, long, default_value = ".")]
     pub dir: String
-}
\ No newline at end of file
+}
+
+impl DirArgs {
+    pub fn path(&self) -> std::io::Result<PathBuf> {
+        if self.dir == "." {
+            std::env::current_dir()
+        } else {
+            Ok(PathBuf::from(&self.dir))
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct MigrateAlgorithmArgs {
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// The algorithm to schedule the deck with
+    #[arg(long)]
+    pub to: RepeatAlgorithm,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// Print the statistics as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("selector").required(true).multiple(true).args(["name", "tag", "id"])))]
+pub struct SelectArgs {
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// Select cards by name
+    #[arg(long)]
+    pub name: Vec<String>,
+    /// Select cards having this tag
+    #[arg(long)]
+    pub tag: Vec<String>,
+    /// Select cards by explicit id or by the id shown in `list`, or an unambiguous prefix of at least 8 characters
+    #[arg(long)]
+    pub id: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// Only list suspended cards
+    #[arg(long)]
+    pub ignored: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct StartArgs {
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// Only study cards matching this tag expression, e.g. "greeting and not sentence"
+    #[arg(long)]
+    pub tag: Vec<String>,
+    /// Skip cards having this tag
+    #[arg(long)]
+    pub exclude_tag: Vec<String>,
+    /// Only study cards from this card file, given relative to its deck directory
+    #[arg(long)]
+    pub file: Vec<String>,
+    /// Type the answer before it is revealed
+    #[arg(long)]
+    pub typed: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AnkiImportArgs {
+    /// The .apkg or .colpkg file
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// Deck name, defaults to the package file name
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Note field (starting at 1) used as card name
+    #[arg(long, default_value_t = 1)]
+    pub name_field: usize,
+    /// Note field used as card content
+    #[arg(long, default_value_t = 2)]
+    pub content_field: usize,
+    /// Note field used as glance
+    #[arg(long)]
+    pub glance_field: Option<usize>,
+    /// Keep the Anki review history and scheduling progress
+    #[arg(long)]
+    pub with_history: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AnkiExportArgs {
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// The .apkg file to write, defaults to the deck name
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// Keep the FSRS scheduling state and review history
+    #[arg(long)]
+    pub with_scheduling: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvFormatArgs {
+    /// Card field of each column: id, name, glance, content, tags or _ to skip
+    #[arg(long)]
+    pub columns: Option<String>,
+    /// Field delimiter, defaults to tab for .tsv files and comma otherwise
+    #[arg(long)]
+    pub delimiter: Option<char>,
+    /// Quote character around fields containing delimiters or line breaks
+    #[arg(long, default_value_t = '"')]
+    pub quote: char,
+    /// Separator between the tags of a card
+    #[arg(long, default_value = " ")]
+    pub tag_separator: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvImportArgs {
+    /// The .csv or .tsv file
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// Deck name if the directory has no deck yet, defaults to the file name
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Card file to append to, defaults to the last card file of the deck
+    #[arg(long)]
+    pub into: Option<String>,
+    #[command(flatten)]
+    pub format: CsvFormatArgs,
+    /// The first row is a header, detected from its cells by default
+    #[arg(long, conflicts_with = "no_header")]
+    pub header: bool,
+    /// The first row is a card
+    #[arg(long)]
+    pub no_header: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CsvExportArgs {
+    #[command(flatten)]
+    pub dir: DirArgs,
+    /// The .csv or .tsv file to write, defaults to the deck name
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    #[command(flatten)]
+    pub format: CsvFormatArgs,
+    /// Do not write the column names as first row
+    #[arg(long)]
+    pub no_header: bool,
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use anyhow::Result;
+use crate::core::deck_loader::{find_deck_meta_file, resolve_card_files};
+use crate::repository::deck::Deck;
+use crate::repository::file_format::{line_column, FileFormat, ParseError, UnknownField};
+use crate::repository::lock::CardItemIdentify;
+
+/// A problem found in a deck file, located by 1-based line and column when possible.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line_column: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.line_column {
+            Some((line, column)) => write!(f, "{}:{}:{}: {}", self.file, line, column, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+/// Where a card was first defined, to point at it from its duplicates.
+struct CardOrigin {
+    file: String,
+    line_column: Option<(usize, usize)>,
+}
+
+struct FileChecker<'a> {
+    file: &'a str,
+    text: &'a str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl FileChecker<'_> {
+    fn report(&mut self, offset: Option<usize>, message: String) {
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_string(),
+            line_column: offset.map(|offset| line_column(self.text, offset)),
+            message,
+        });
+    }
+    fn report_parse_error(&mut self, error: ParseError) {
+        self.diagnostics.push(Diagnostic {
+            file: self.file.to_string(),
+            line_column: error.line_column,
+            message: error.message,
+        });
+    }
+    fn find(&self, needle: &str, from: usize) -> Option<usize> {
+        self.text.get(from..)?.find(needle).map(|found| from + found)
+    }
+    /// Offset of the name of the next card after `from`, found at its `name` key.
+    fn find_card(&self, name: &str, from: usize) -> Option<usize> {
+        let mut from = from;
+        while let Some(key) = self.find("name", from) {
+            let value_start = key + "name".len();
+            let line_end = self.find("\n", value_start).unwrap_or(self.text.len());
+            if name.is_empty() {
+                return Some(key);
+            }
+            if let Some(found) = self.text[value_start..line_end].find(name) {
+                return Some(value_start + found);
+            }
+            from = value_start;
+        }
+        None
+    }
+    fn report_unknown_fields(&mut self, unknown: &[UnknownField], card_offsets: &[Option<usize>]) {
+        for field in unknown {
+            let from = field.card.and_then(|card| card_offsets.get(card).copied().flatten()).unwrap_or(0);
+            let offset = self.find(&field.key, from);
+            let message = match field.card {
+                Some(card) => format!("unknown field `{}` in card {}", field.key, card + 1),
+                None => format!("unknown field `{}`", field.key),
+            };
+            self.report(offset, message);
+        }
+    }
+}
+
+/// Check the deck metadata of `text`, returning the parsed deck when it can be read.
+fn check_deck_meta(file: &str, text: &str, format: FileFormat) -> (Option<Deck>, Vec<Diagnostic>) {
+    let mut checker = FileChecker { file, text, diagnostics: Vec::new() };
+    let (deck, unknown) = match format.parse_checked::<Deck>(text) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            checker.report_parse_error(error);
+            return (None, checker.diagnostics);
+        }
+    };
+    checker.report_unknown_fields(&unknown, &[]);
+    if let Some(ref fsrs_option) = deck.fsrs_option {
+        if !(fsrs_option.retention > 0.0 && fsrs_option.retention < 1.0) {
+            let offset = checker.find("retention", 0);
+            checker.report(offset, format!("retention {} is out of range, it must be between 0 and 1", fsrs_option.retention));
+        }
+    }
+    (Some(deck), checker.diagnostics)
+}
+
+/// Check the cards of one card file, recording their ids in `seen` to find duplicates across files.
+fn check_cards(file: &str, text: &str, format: FileFormat, seen: &mut HashMap<CardItemIdentify, CardOrigin>) -> Vec<Diagnostic> {
+    let mut checker = FileChecker { file, text, diagnostics: Vec::new() };
+    let (cards, unknown) = match format.parse_cards_checked(text) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            checker.report_parse_error(error);
+            return checker.diagnostics;
+        }
+    };
+
+    // cards appear in file order, so searching each name after the previous one finds its entry
+    let mut cursor = 0;
+    let card_offsets: Vec<_> = cards.iter().map(|card| {
+        let offset = checker.find_card(&card.name, cursor);
+        if let Some(offset) = offset {
+            cursor = offset + card.name.len().max(1);
+        }
+        offset
+    }).collect();
+    checker.report_unknown_fields(&unknown, &card_offsets);
+
+    for (index, card) in cards.iter().enumerate() {
+        let offset = card_offsets[index];
+        if card.name.trim().is_empty() {
+            checker.report(offset, format!("card {} has an empty name", index + 1));
+        }
+        if card.content.trim().is_empty() {
+            checker.report(offset, format!("card \"{}\" has an empty content", card.name));
+        }
+        let line_column = offset.map(|offset| line_column(text, offset));
+        match seen.get(&card.get_id()) {
+            Some(origin) => {
+                let first = match origin.line_column {
+                    Some((line, column)) => format!("{}:{}:{}", origin.file, line, column),
+                    None => origin.file.clone(),
+                };
+                let message = match card.id {
+                    Some(ref id) => format!("duplicate id \"{}\", first used at {}", id, first),
+                    None => format!("duplicate card \"{}\", first defined at {}", card.name, first),
+                };
+                checker.report(offset, message);
+            }
+            None => {
+                seen.insert(card.get_id(), CardOrigin { file: file.to_string(), line_column });
+            }
+        }
+    }
+    checker.diagnostics
+}
+
+/// Validate the deck metadata and every card file of the deck in `dir`.
+pub async fn check(dir: &Path) -> Result<Vec<Diagnostic>> {
+    let Some(meta_file) = find_deck_meta_file(dir).await? else {
+        return Ok(vec![Diagnostic {
+            file: dir.display().to_string(),
+            line_column: None,
+            message: "deck metadata file (deck.yaml, deck.json or deck.toml) not found".to_string(),
+        }]);
+    };
+    let meta_name = meta_file.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let meta_text = tokio::fs::read_to_string(&meta_file).await?;
+    let (deck, mut diagnostics) = check_deck_meta(&meta_name, &meta_text, FileFormat::from_path(&meta_file)?);
+    let Some(deck) = deck else {
+        return Ok(diagnostics);
+    };
+
+    let card_files = match resolve_card_files(dir, &deck).await {
+        Ok(card_files) => card_files,
+        Err(error) => {
+            diagnostics.push(Diagnostic { file: meta_name, line_column: None, message: format!("{:#}", error) });
+            return Ok(diagnostics);
+        }
+    };
+    let mut seen = HashMap::new();
+    for card_file in &card_files {
+        let path = dir.join(card_file);
+        let format = match FileFormat::from_path(&path) {
+            Ok(format) => format,
+            Err(error) => {
+                let mut checker = FileChecker { file: &meta_name, text: &meta_text, diagnostics: Vec::new() };
+                checker.report(checker.find(card_file, 0), error.to_string());
+                diagnostics.append(&mut checker.diagnostics);
+                continue;
+            }
+        };
+        if !tokio::fs::try_exists(&path).await? {
+            let mut checker = FileChecker { file: &meta_name, text: &meta_text, diagnostics: Vec::new() };
+            checker.report(checker.find(card_file, 0), format!("card file {} not found", card_file));
+            diagnostics.append(&mut checker.diagnostics);
+            continue;
+        }
+        let text = tokio::fs::read_to_string(&path).await?;
+        diagnostics.extend(check_cards(card_file, &text, format, &mut seen));
+    }
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use crate::repository::file_format::FileFormat;
+    use super::{check_cards, check_deck_meta};
+
+    #[test]
+    fn test_check_cards() {
+        let text = "- name: 猫\n  content: cat\n- name: 犬\n  contnet: dog\n  content: \"\"\n- name: 猫\n  content: cat\n";
+        let mut seen = HashMap::new();
+        let messages: Vec<_> = check_cards("cards.yml", text, FileFormat::Yaml, &mut seen)
+            .iter().map(ToString::to_string).collect();
+        assert_eq!(messages, vec![
+            "cards.yml:4:3: unknown field `contnet` in card 2",
+            "cards.yml:3:9: card \"犬\" has an empty content",
+            "cards.yml:6:9: duplicate card \"猫\", first defined at cards.yml:1:9",
+        ]);
+
+        let broken = "- name: 猫\n  content: [cat\n";
+        let diagnostics = check_cards("broken.yml", broken, FileFormat::Yaml, &mut seen);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].line_column.is_some());
+    }
+
+    #[test]
+    fn test_check_deck_meta() {
+        let text = "name = \"deck\"\ncard_files = []\n\n[fsrs_option]\nretention = 1.5\n";
+        let (deck, diagnostics) = check_deck_meta("deck.toml", text, FileFormat::Toml);
+        assert!(deck.is_some());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line_column, Some((5, 1)));
+
+        let text = "name: deck\ncard_files: []\nday_rollover_hour: 24\n";
+        let (deck, diagnostics) = check_deck_meta("deck.yaml", text, FileFormat::Yaml);
+        assert!(deck.is_none());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("day_rollover_hour 24 is not an hour of the day"));
+    }
+}
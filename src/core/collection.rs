@@ -0,0 +1,284 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use crate::core::learning::Difficulty;
+use crate::core::tag_filter::{same_file, StudyFilter};
+use crate::core::{Progress, RunningCore, UNDO_LIMIT};
+use crate::repository::collection::CollectionConfig;
+use crate::repository::deck::{CardItem, TypedAnswerOption};
+use crate::repository::file_format::FileFormat;
+use crate::repository::lock::CardItemIdentify;
+
+pub(crate) const COLLECTION_FILES: [&str; 4] = ["collection.yaml", "collection.yml", "collection.json", "collection.toml"];
+const DECK_FILES: [&str; 4] = ["deck.yaml", "deck.yml", "deck.json", "deck.toml"];
+
+/// A card of one of the decks of a [`Collection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CardRef {
+    pub deck: usize,
+    pub id: CardItemIdentify,
+}
+
+/// Decks studied in one session, each keeping its own daily limits, lock file and review log.
+pub struct Collection {
+    name: String,
+    decks: Vec<RunningCore>,
+    /// Decks of the answers that can be undone, latest last.
+    history: Vec<usize>,
+}
+
+fn is_hidden(entry: &walkdir::DirEntry) -> bool {
+    entry.depth() > 0 && entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// Keep the first of the directories resolving to the same canonical path.
+fn unique_dirs(dirs: Vec<PathBuf>) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    dirs.into_iter().filter(|dir| seen.insert(dir.clone())).collect()
+}
+
+/// The name and deck directories of the collection at `dir`.
+///
+/// Decks come from the collection file when there is one, otherwise from every directory below
+/// `dir` (including itself) holding deck metadata, in path order.
+pub async fn discover_decks(dir: &Path) -> Result<(String, Vec<PathBuf>)> {
+    let dir_name = dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+    for file_name in COLLECTION_FILES {
+        let file = dir.join(file_name);
+        if !tokio::fs::try_exists(&file).await? {
+            continue;
+        }
+        let content = tokio::fs::read_to_string(&file).await?;
+        let config: CollectionConfig = FileFormat::from_path(&file)?.parse(&content)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let mut decks = Vec::new();
+        for deck in &config.decks {
+            let deck_dir = tokio::fs::canonicalize(dir.join(deck)).await
+                .with_context(|| format!("Deck directory `{}` listed in {} does not exist", deck, file.display()))?;
+            decks.push(deck_dir);
+        }
+        return Ok((config.name.unwrap_or(dir_name), unique_dirs(decks)));
+    }
+    let root = dir.to_path_buf();
+    let decks = tokio::task::spawn_blocking(move || -> Result<Vec<PathBuf>> {
+        let mut decks = Vec::new();
+        let walker = walkdir::WalkDir::new(&root).sort_by_file_name().into_iter().filter_entry(|entry| !is_hidden(entry));
+        for entry in walker {
+            let entry = entry?;
+            let is_deck_file = DECK_FILES.iter().any(|name| entry.file_name().eq_ignore_ascii_case(name));
+            if entry.file_type().is_file() && is_deck_file {
+                if let Some(deck_dir) = entry.path().parent() {
+                    decks.push(std::fs::canonicalize(deck_dir)?);
+                }
+            }
+        }
+        Ok(unique_dirs(decks))
+    }).await??;
+    Ok((dir_name, decks))
+}
+
+impl Collection {
+    pub fn new(name: String, decks: Vec<RunningCore>) -> Self {
+        Collection { name, decks, history: Vec::new() }
+    }
+    /// The collection name, or the deck name when it holds a single deck.
+    pub fn name(&self) -> &str {
+        match self.decks.as_slice() {
+            [deck] => deck.deck_name(),
+            _ => &self.name,
+        }
+    }
+    pub fn decks(&self) -> &[RunningCore] {
+        &self.decks
+    }
+    pub fn card(&self, card: CardRef) -> &CardItem {
+        &self.decks[card.deck].cards[&card.id]
+    }
+    pub fn deck_name(&self, card: CardRef) -> &str {
+        self.decks[card.deck].deck_name()
+    }
+    pub fn typed_answer(&self, card: CardRef) -> Option<&TypedAnswerOption> {
+        self.decks[card.deck].typed_answer()
+    }
+    pub fn preview_intervals(&self, card: CardRef) -> [(Difficulty, f32); 4] {
+        self.decks[card.deck].preview_intervals(card.id)
+    }
+    /// Only study the cards matching `filter`, failing when one of its files is no card file of any deck.
+    pub fn restrict(&mut self, filter: &StudyFilter) -> Result<()> {
+        for selected in &filter.files {
+            let found = self.decks.iter()
+                .any(|deck| deck.files.values().any(|file| same_file(&deck.working_dir, selected, file)));
+            if !found {
+                bail!("{} is not a card file of {}", selected, self.name());
+            }
+        }
+        for deck in &mut self.decks {
+            deck.restrict(filter);
+        }
+        Ok(())
+    }
+    pub fn progress(&self) -> Progress {
+        self.decks.iter().map(RunningCore::progress).fold(Progress::default(), |total, progress| Progress {
+            due: total.due + progress.due,
+            new: total.new + progress.new,
+            done: total.done + progress.done,
+        })
+    }
+    /// The earliest card in learning steps of any deck, see [`RunningCore::next_learning`].
+    fn next_learning(&self, ahead: bool) -> Option<CardRef> {
+        self.decks.iter().enumerate()
+            .filter_map(|(deck, core)| core.next_learning(ahead).map(|(id, due)| (CardRef { deck, id }, due)))
+            .min_by_key(|(_, due)| *due)
+            .map(|(card, _)| card)
+    }
+    /// Due learning steps, due reviews of all decks by retrievability, then new cards deck by deck.
+    pub fn next_card(&self) -> Option<CardRef> {
+        if let Some(card) = self.next_learning(false) {
+            return Some(card);
+        }
+        let review = self.decks.iter().enumerate()
+            .filter_map(|(deck, core)| core.next_review().map(|(id, retrievability)| (CardRef { deck, id }, retrievability)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((card, _)) = review {
+            return Some(card);
+        }
+        let new = self.decks.iter().enumerate()
+            .find_map(|(deck, core)| core.next_new().map(|id| CardRef { deck, id }));
+        // rather than ending the session, show cards whose learning step ends in a few minutes
+        new.or_else(|| self.next_learning(true))
+    }
+    /// When the next card in learning steps of any deck is due today, once [`Collection::next_card`] has none.
+    pub fn next_learning_due(&self) -> Option<DateTime<Utc>> {
+        self.decks.iter().filter_map(RunningCore::next_learning_due).min()
+    }
+    fn push_history(&mut self, deck: usize) {
+        if self.history.len() == UNDO_LIMIT {
+            self.history.remove(0);
+        }
+        self.history.push(deck);
+    }
+    pub async fn next_state(&mut self, card: CardRef, difficulty: Difficulty, time_spent: Duration) -> Result<()> {
+        self.decks[card.deck].next_state(card.id, difficulty, time_spent).await?;
+        self.push_history(card.deck);
+        Ok(())
+    }
+    pub async fn set_ignored(&mut self, card: CardRef) -> Result<()> {
+        self.decks[card.deck].set_ignored(card.id).await?;
+        self.push_history(card.deck);
+        Ok(())
+    }
+    /// Revert the last answer or ignore in any deck and return the card to show again.
+    pub async fn undo(&mut self) -> Result<Option<CardRef>> {
+        let Some(deck) = self.history.pop() else {
+            return Ok(None);
+        };
+        let id = self.decks[deck].undo().await?;
+        Ok(id.map(|id| CardRef { deck, id }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::time::Duration;
+    use crate::core::deck_loader::{create_or_update_lock_file, read_deck_meta_file};
+    use crate::core::learning::Difficulty;
+    use crate::core::RunningCore;
+    use crate::core::tag_filter::StudyFilter;
+    use super::{discover_decks, Collection};
+
+    fn write_deck(dir: &Path, name: &str, cards: &[&str]) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join("deck.yaml"), format!("name: {name}\ncard_files: [cards.yml]\nnew_per_day: 1\n")).unwrap();
+        let cards: String = cards.iter().map(|card| format!("- name: {card}\n  content: {card}\n")).collect();
+        std::fs::write(dir.join("cards.yml"), cards).unwrap();
+    }
+
+    async fn open(dirs: &[&Path]) -> Collection {
+        let mut decks = Vec::new();
+        for dir in dirs {
+            let deck = read_deck_meta_file(dir).await.unwrap();
+            let loaded = create_or_update_lock_file(dir, &deck).await.unwrap();
+            decks.push(RunningCore::new(dir.to_path_buf(), deck, loaded).await.unwrap());
+        }
+        Collection::new("collection".to_string(), decks)
+    }
+
+    #[tokio::test]
+    async fn test_discover_decks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        for deck in ["b", "a", "a/sub", ".hidden"] {
+            write_deck(&root.join(deck), deck, &[]);
+        }
+        let (_, decks) = discover_decks(&root).await.unwrap();
+        assert_eq!(decks, vec![root.join("a"), root.join("a/sub"), root.join("b")]);
+
+        std::fs::write(root.join("collection.yaml"), "name: Languages\ndecks: [b, ./b, a/sub, a/../b]\n").unwrap();
+        let (name, decks) = discover_decks(&root).await.unwrap();
+        assert_eq!(name, "Languages");
+        assert_eq!(decks, vec![root.join("b"), root.join("a/sub")]);
+
+        std::fs::write(root.join("collection.yaml"), "decks: [b, missing]\n").unwrap();
+        let error = discover_decks(&root).await.unwrap_err();
+        assert!(error.to_string().contains("`missing`"));
+    }
+
+    #[tokio::test]
+    async fn test_limits_stay_per_deck() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        write_deck(&a, "a", &["a1", "a2"]);
+        write_deck(&b, "b", &["b1", "b2"]);
+        let mut collection = open(&[&a, &b]).await;
+        assert_eq!(collection.progress().new, 2);
+
+        let mut studied = Vec::new();
+        while let Some(card) = collection.next_card() {
+            studied.push(collection.card(card).name.clone());
+            collection.next_state(card, Difficulty::Good, Duration::ZERO).await.unwrap();
+        }
+        assert_eq!(studied, vec!["a1", "b1"]);
+        assert_eq!(collection.progress().done, 2);
+    }
+
+    #[tokio::test]
+    async fn test_next_card_after_undo() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        write_deck(&a, "a", &["a1"]);
+        write_deck(&b, "b", &["b1"]);
+        let mut collection = open(&[&a, &b]).await;
+        let first = collection.next_card().unwrap();
+        collection.next_state(first, Difficulty::Good, Duration::ZERO).await.unwrap();
+        let second = collection.next_card().unwrap();
+        assert_eq!(second.deck, 1);
+        collection.set_ignored(second).await.unwrap();
+        assert_eq!(collection.next_card(), None);
+
+        assert_eq!(collection.undo().await.unwrap(), Some(second));
+        assert_eq!(collection.next_card(), Some(second));
+        assert_eq!(collection.undo().await.unwrap(), Some(first));
+        assert_eq!(collection.next_card(), Some(first));
+        assert_eq!(collection.progress().done, 0);
+        assert_eq!(collection.undo().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_restrict_to_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        write_deck(&a, "a", &["a1"]);
+        write_deck(&b, "b", &["b1"]);
+        let mut collection = open(&[&a, &b]).await;
+        let filter = StudyFilter { files: vec!["./cards.yml".to_string()], ..StudyFilter::default() };
+        collection.restrict(&filter).unwrap();
+        assert_eq!(collection.progress().new, 2);
+
+        let filter = StudyFilter { files: vec!["a/cards.yml".to_string()], ..StudyFilter::default() };
+        let error = collection.restrict(&filter).unwrap_err();
+        assert!(error.to_string().contains("a/cards.yml"));
+    }
+}
@@ -1,13 +1,21 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::Serialize;
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use crate::core::collection::COLLECTION_FILES;
+use crate::repository::daily_counter::DailyCounter;
 use crate::repository::deck::{CardItem, Deck};
-use crate::repository::lock::{update_lock_item_list, CardItemIdentify, LockItem};
+use crate::repository::file_format::FileFormat;
+use crate::repository::lock::{update_lock_item_list, CardItemIdentify, LockItem, LockMigration};
+use crate::repository::review_log::{ReviewLogAlias, ReviewLogEntry, ReviewLogLine};
 
 const DECK_METADATA_1: &str = "deck.yaml";
 const DECK_METADATA_2: &str = "deck.yml";
 const DECK_METADATA_3: &str = "deck.json";
+const DECK_METADATA_4: &str = "deck.toml";
 
 pub async fn find_deck_meta_file(dir: &Path) -> Result<Option<PathBuf>> {
     let mut dir = fs::read_dir(dir).await?;
@@ -16,7 +24,7 @@ pub async fn find_deck_meta_file(dir: &Path) -> Result<Option<PathBuf>> {
         if path.is_file() {
             if let Some(file_name) = path.file_name() {
                 let file_name = file_name.to_ascii_lowercase();
-                if file_name == DECK_METADATA_1 || file_name == DECK_METADATA_2 || file_name == DECK_METADATA_3 {
+                if [DECK_METADATA_1, DECK_METADATA_2, DECK_METADATA_3, DECK_METADATA_4].iter().any(|name| file_name == *name) {
                     return Ok(Some(path));
                 }
             }
@@ -24,15 +32,84 @@ pub async fn find_deck_meta_file(dir: &Path) -> Result<Option<PathBuf>> {
     }
     Ok(None)
 }
+/// Deck settings a sub-deck takes from its parent decks unless it sets them itself.
+const INHERITED_FIELDS: [&str; 10] = [
+    "algorithm", "fsrs_option", "new_per_day", "reviews_per_day", "new_card_order", "day_rollover_hour", "direction", "typed_answer",
+    "learning_steps", "relearning_steps",
+];
+
+async fn read_deck_meta_value(meta_file: &Path) -> Result<serde_json::Value> {
+    let content = fs::read_to_string(meta_file).await?;
+    FileFormat::from_path(meta_file)?.parse(&content)
+        .with_context(|| format!("Failed to read {}", meta_file.display()))
+}
+
+/// Read the deck metadata in `dir`, filling the settings it leaves out from the decks in the
+/// directories above it, nearest first, up to the first directory without a deck.
 pub async fn read_deck_meta_file(dir: &Path) -> Result<Deck> {
-    let meta_file = find_deck_meta_file(dir).await?;
-    if let Some(meta_file) = meta_file {
-        let content = fs::read_to_string(meta_file).await?;
-        let deck: Deck = serde_yaml::from_str(&content)?;
-        Ok(deck)
-    } else {
-        Err(anyhow::anyhow!("Deck metadata file not found"))
+    let Some(meta_file) = find_deck_meta_file(dir).await? else {
+        return Err(anyhow::anyhow!("Deck metadata file not found"));
+    };
+    let mut value = read_deck_meta_value(&meta_file).await?;
+    let mut inherited = serde_json::Map::new();
+    let dir = fs::canonicalize(dir).await?;
+    let mut parent = dir.parent();
+    while let Some(parent_dir) = parent {
+        let Some(parent_file) = find_deck_meta_file(parent_dir).await? else {
+            break;
+        };
+        let parent_value = read_deck_meta_value(&parent_file).await?;
+        for field in INHERITED_FIELDS {
+            if value.get(field).is_none() && !inherited.contains_key(field) {
+                if let Some(setting) = parent_value.get(field) {
+                    inherited.insert(field.to_string(), setting.clone());
+                }
+            }
+        }
+        parent = parent_dir.parent();
     }
+    if let Some(fields) = value.as_object_mut() {
+        fields.extend(inherited.clone());
+    }
+    let mut deck: Deck = serde_json::from_value(value)
+        .with_context(|| format!("Failed to read {}", meta_file.display()))?;
+    // keep inherited settings as the deck serializes them, so that unchanged ones are recognized when writing
+    let serialized = serde_json::to_value(&deck)?;
+    for (field, setting) in inherited.iter_mut() {
+        if let Some(normalized) = serialized.get(field) {
+            *setting = normalized.clone();
+        }
+    }
+    deck.inherited = inherited;
+    Ok(deck)
+}
+
+pub async fn write_deck_meta_file(dir: &Path, deck: &Deck) -> Result<()> {
+    let meta_file = find_deck_meta_file(dir).await?.unwrap_or_else(|| dir.join(DECK_METADATA_1));
+    let format = FileFormat::from_path(&meta_file)?;
+    let content = if deck.inherited.is_empty() {
+        format.serialize(deck)?
+    } else {
+        let mut value = serde_json::to_value(deck)?;
+        if let Some(fields) = value.as_object_mut() {
+            fields.retain(|field, setting| deck.inherited.get(field) != Some(setting));
+        }
+        format.serialize(&value)?
+    };
+    fs::write(meta_file, content).await?;
+    Ok(())
+}
+
+/// Set the `key` setting of the deck metadata in `dir`, leaving the rest of the file as written.
+pub async fn update_deck_meta_key<T: Serialize>(dir: &Path, key: &str, value: &T) -> Result<()> {
+    let Some(meta_file) = find_deck_meta_file(dir).await? else {
+        return Err(anyhow::anyhow!("Deck metadata file not found"));
+    };
+    let content = fs::read_to_string(&meta_file).await?;
+    let edited = FileFormat::from_path(&meta_file)?.set_key(&content, key, &serde_json::to_value(value)?)
+        .with_context(|| format!("Failed to update {}", meta_file.display()))?;
+    fs::write(meta_file, edited).await?;
+    Ok(())
 }
 
 const LOCK_FILE: &str = "deck.lock";
@@ -54,23 +131,386 @@ pub async fn write_lock_file(dir: &Path, lock_list: &Vec<LockItem>) -> Result<()
     Ok(())
 }
 
-async fn read_cards(file: &Path) -> Result<Vec<CardItem>> {
+const DAILY_COUNTER_FILE: &str = "daily_counter.json";
+
+pub async fn read_daily_counter(dir: &Path) -> Result<Option<DailyCounter>> {
+    let counter_file = dir.join(DAILY_COUNTER_FILE);
+    if !counter_file.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(counter_file).await?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+pub async fn write_daily_counter(dir: &Path, counter: &DailyCounter) -> Result<()> {
+    let counter_file = dir.join(DAILY_COUNTER_FILE);
+    let content = serde_json::to_string_pretty(counter)?;
+    fs::write(counter_file, content).await?;
+    Ok(())
+}
+
+const REVIEW_LOG_FILE: &str = "review_log.jsonl";
+
+pub async fn read_review_log(dir: &Path) -> Result<Vec<ReviewLogEntry>> {
+    let log_file = dir.join(REVIEW_LOG_FILE);
+    if !log_file.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(log_file).await?;
+    let mut entries: Vec<ReviewLogEntry> = Vec::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let entry = match ReviewLogLine::parse(line)? {
+            ReviewLogLine::Answer(entry) => entry,
+            ReviewLogLine::Alias(ReviewLogAlias { from, to: CardItemIdentify(name, content) }) => {
+                for entry in entries.iter_mut().filter(|entry| entry.get_id() == from) {
+                    entry.name = name;
+                    entry.content = content;
+                }
+                continue;
+            }
+        };
+        if !entry.undone {
+            entries.push(entry);
+            continue;
+        }
+        // an undone answer is dropped together with the entry marking it
+        let undone = entries.iter().rposition(|answer| answer.get_id() == entry.get_id() && answer.reviewed_at == entry.reviewed_at);
+        if let Some(position) = undone {
+            entries.remove(position);
+        }
+    }
+    Ok(entries)
+}
+
+pub async fn write_review_log(dir: &Path, entries: &[ReviewLogEntry]) -> Result<()> {
+    let log_file = dir.join(REVIEW_LOG_FILE);
+    let mut content = String::new();
+    for entry in entries {
+        content.push_str(&serde_json::to_string(entry)?);
+        content.push('\n');
+    }
+    fs::write(log_file, content).await?;
+    Ok(())
+}
+
+/// Point the review history of migrated cards to their new identity, by appending an alias for
+/// each card with logged answers rather than rewriting them.
+async fn migrate_review_log(dir: &Path, migrations: &[LockMigration]) -> Result<()> {
+    let logged: HashSet<_> = read_review_log(dir).await?.iter().map(ReviewLogEntry::get_id).collect();
+    let mut lines = String::new();
+    for migration in migrations.iter().filter(|migration| logged.contains(&migration.from)) {
+        lines.push_str(&serde_json::to_string(&ReviewLogAlias { from: migration.from, to: migration.to })?);
+        lines.push('\n');
+    }
+    if !lines.is_empty() {
+        let mut file = fs::OpenOptions::new().append(true).open(dir.join(REVIEW_LOG_FILE)).await?;
+        file.write_all(lines.as_bytes()).await?;
+        file.flush().await?;
+    }
+    Ok(())
+}
+
+/// Append an answer to the review log, or with `undone` set, mark an earlier answer as undone.
+pub async fn append_review_log(dir: &Path, entry: &ReviewLogEntry) -> Result<()> {
+    let log_file = dir.join(REVIEW_LOG_FILE);
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(log_file).await?;
+    file.write_all(line.as_bytes()).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+pub async fn read_cards(file: &Path) -> Result<Vec<CardItem>> {
     let content = fs::read_to_string(file).await?;
-    let cards: Vec<CardItem> = serde_yaml::from_str(&content)?;
+    let cards = FileFormat::from_path(file)?.parse_cards(&content)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
     Ok(cards)
 }
 
-pub async fn create_or_update_lock_file(dir: &Path, deck: &Deck) -> Result<HashMap<CardItemIdentify, CardItem>> {
+/// File names that are never card files, in the deck directory or below it, even if a pattern matches them.
+const RESERVED_FILES: [&str; 7] = [
+    DECK_METADATA_1, DECK_METADATA_2, DECK_METADATA_3, DECK_METADATA_4, LOCK_FILE, REVIEW_LOG_FILE, DAILY_COUNTER_FILE,
+];
+
+fn is_reserved(file: &str) -> bool {
+    let name = file.rsplit('/').next().unwrap_or(file);
+    RESERVED_FILES.contains(&name) || COLLECTION_FILES.contains(&name)
+}
+
+fn relative_name(dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?;
+    let parts: Vec<_> = relative.components().map(|part| part.as_os_str().to_string_lossy()).collect();
+    Some(parts.join("/"))
+}
+
+/// Whether `file` lies in a sub-deck of the deck at `dir`, whose cards belong to that sub-deck.
+fn in_sub_deck(dir: &Path, file: &str) -> bool {
+    let path = dir.join(file);
+    path.ancestors().skip(1)
+        .take_while(|ancestor| *ancestor != dir)
+        .any(|ancestor| [DECK_METADATA_1, DECK_METADATA_2, DECK_METADATA_3, DECK_METADATA_4].iter().any(|name| ancestor.join(name).is_file()))
+}
+
+fn expand_card_file(dir: &Path, entry: &str) -> Result<Vec<String>> {
+    let path = dir.join(entry);
+    let mut files = Vec::new();
+    if entry.contains(['*', '?', '[']) {
+        let pattern = format!("{}/{}", glob::Pattern::escape(&dir.to_string_lossy()), entry);
+        let matches = glob::glob(&pattern).with_context(|| format!("Invalid card file pattern {}", entry))?;
+        for matched in matches {
+            let matched = matched?;
+            if matched.is_file() && FileFormat::from_path(&matched).is_ok() {
+                files.extend(relative_name(dir, &matched));
+            }
+        }
+    } else if path.is_dir() {
+        for found in walkdir::WalkDir::new(&path) {
+            let found = found?;
+            if found.file_type().is_file() && FileFormat::from_path(found.path()).is_ok() {
+                files.extend(relative_name(dir, found.path()));
+            }
+        }
+    } else if in_sub_deck(dir, entry) {
+        // listed by a parent deck, the file still belongs to its sub-deck
+        return Ok(Vec::new());
+    } else {
+        return Ok(vec![entry.to_string()]);
+    }
+    files.retain(|file| !is_reserved(file) && !in_sub_deck(dir, file));
+    files.sort();
+    Ok(files)
+}
+
+/// Expand the glob patterns and directories of `Deck::card_files` into card files relative to `dir`,
+/// in the order of the entries and sorted by path within each entry.
+pub async fn resolve_card_files(dir: &Path, deck: &Deck) -> Result<Vec<String>> {
+    let excludes = deck.exclude_files.iter()
+        .map(|pattern| glob::Pattern::new(pattern).with_context(|| format!("Invalid exclude pattern {}", pattern)))
+        .collect::<Result<Vec<_>>>()?;
+    let dir = dir.to_path_buf();
+    let entries = deck.card_files.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut files: Vec<String> = Vec::new();
+        for entry in &entries {
+            for file in expand_card_file(&dir, entry)? {
+                if !files.contains(&file) && !excludes.iter().any(|pattern| pattern.matches(&file)) {
+                    files.push(file);
+                }
+            }
+        }
+        Ok(files)
+    }).await?
+}
+
+pub async fn write_cards(file: &Path, cards: &[CardItem]) -> Result<()> {
+    let content = FileFormat::from_path(file)?.serialize_cards(cards)?;
+    fs::write(file, content).await?;
+    Ok(())
+}
+
+/// Add `cards` to the end of a card file, creating it when missing, see [`FileFormat::append_cards`].
+pub async fn append_cards(file: &Path, cards: &[CardItem]) -> Result<()> {
+    let content = if fs::try_exists(file).await? { fs::read_to_string(file).await? } else { String::new() };
+    let appended = FileFormat::from_path(file)?.append_cards(&content, cards)
+        .with_context(|| format!("Failed to update {}", file.display()))?;
+    fs::write(file, appended).await?;
+    Ok(())
+}
+
+pub struct LoadedCards {
+    pub cards: HashMap<CardItemIdentify, CardItem>,
+    /// Card ids in the order they appear in the card files.
+    pub order: Vec<CardItemIdentify>,
+    /// The card file each card was read from, as listed in the deck.
+    pub files: HashMap<CardItemIdentify, String>,
+    /// The other cards generated from the same entry, like the other direction or the other cloze deletions.
+    pub siblings: HashMap<CardItemIdentify, Vec<CardItemIdentify>>,
+    pub migrations: Vec<LockMigration>,
+}
+
+pub async fn create_or_update_lock_file(dir: &Path, deck: &Deck) -> Result<LoadedCards> {
     let existing_lock = read_lock_file(dir).await.unwrap_or_else(|_| Vec::new());
-    let cards_files_name = &deck.card_files;
+    let cards_files_name = resolve_card_files(dir, deck).await?;
     let mut cards = Vec::new();
-    for file_name in cards_files_name {
+    let mut card_sources = Vec::new();
+    let mut siblings = HashMap::new();
+    for file_name in &cards_files_name {
         let file = dir.join(file_name);
-        let file_cards = read_cards(&file).await?;
+        let mut file_cards = Vec::new();
+        for card in read_cards(&file).await? {
+            let variants = card.variant_cards(deck.direction);
+            if variants.len() > 1 {
+                let ids: Vec<_> = variants.iter().map(|variant| variant.get_id()).collect();
+                for id in &ids {
+                    siblings.insert(*id, ids.iter().filter(|other| *other != id).copied().collect());
+                }
+            }
+            file_cards.extend(variants);
+        }
+        card_sources.extend(std::iter::repeat_n(file_name, file_cards.len()));
         cards.extend(file_cards);
     }
-    let new_lock = update_lock_item_list(existing_lock, &cards);
+    let (new_lock, migrations) = update_lock_item_list(existing_lock, &cards);
     write_lock_file(dir, &new_lock).await?;
-    let cards: HashMap<CardItemIdentify, CardItem> = cards.into_iter().map(|card| (card.get_id(), card)).collect();
-    Ok(cards)
-}
\ No newline at end of file
+    if !migrations.is_empty() {
+        migrate_review_log(dir, &migrations).await?;
+    }
+    let mut order = Vec::new();
+    let mut files = HashMap::new();
+    let mut cards_map = HashMap::new();
+    for (card, file_name) in cards.into_iter().zip(card_sources) {
+        let id = card.get_id();
+        if let Entry::Vacant(entry) = cards_map.entry(id) {
+            order.push(id);
+            files.insert(id, file_name.clone());
+            entry.insert(card);
+        }
+    }
+    Ok(LoadedCards { cards: cards_map, order, files, siblings, migrations })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Local;
+    use crate::repository::daily_counter::DailyCounter;
+    use crate::repository::deck::Deck;
+    use super::{append_review_log, create_or_update_lock_file, read_deck_meta_file, read_review_log, resolve_card_files,
+                write_daily_counter, write_deck_meta_file, LOCK_FILE, REVIEW_LOG_FILE};
+
+    #[tokio::test]
+    async fn test_resolve_card_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["deck.yaml", "daily_counter.json", "intro.yml", "chapters/b/2.yml", "chapters/a/1.json", "chapters/a/draft.yml", "chapters/notes.txt"] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "[]").unwrap();
+        }
+        let mut deck = Deck::new("test".to_string(), vec!["intro.yml".to_string(), "chapters".to_string(), "*.json".to_string(), "**/*.yml".to_string()]);
+        deck.exclude_files = vec!["**/draft.yml".to_string()];
+        let files = resolve_card_files(dir.path(), &deck).await.unwrap();
+        assert_eq!(files, vec!["intro.yml", "chapters/a/1.json", "chapters/b/2.yml"]);
+    }
+
+    #[tokio::test]
+    async fn test_sub_deck_files_listed_by_parent() {
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["intro.yml", "n5/deck.yaml", "n5/vocab.yml"] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "[]").unwrap();
+        }
+        let deck = Deck::new("test".to_string(), vec!["intro.yml".to_string(), "n5/vocab.yml".to_string(), "./n5/vocab.yml".to_string()]);
+        assert_eq!(resolve_card_files(dir.path(), &deck).await.unwrap(), vec!["intro.yml"]);
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_skips_deck_state_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("deck.yaml"), "name: test\ncard_files: ['*']\n").unwrap();
+        std::fs::write(dir.path().join("cards.yaml"), "- name: a\n  content: b\n").unwrap();
+        std::fs::write(dir.path().join("collection.yaml"), "decks: ['.']\n").unwrap();
+        let deck = read_deck_meta_file(dir.path()).await.unwrap();
+        create_or_update_lock_file(dir.path(), &deck).await.unwrap();
+        write_daily_counter(dir.path(), &DailyCounter::new(Local::now().date_naive())).await.unwrap();
+        assert!(dir.path().join(LOCK_FILE).is_file());
+
+        assert_eq!(resolve_card_files(dir.path(), &deck).await.unwrap(), vec!["cards.yaml"]);
+        let loaded = create_or_update_lock_file(dir.path(), &deck).await.unwrap();
+        assert_eq!(loaded.order.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_undone_answers_are_appended_and_skipped() {
+        use crate::core::learning::Difficulty;
+        use crate::repository::lock::SchedulerMemory;
+        use crate::repository::review_log::ReviewLogEntry;
+        let dir = tempfile::tempdir().unwrap();
+        let card = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, b"card");
+        let answer = |day: i64, rating: Difficulty| ReviewLogEntry {
+            name: card,
+            content: card,
+            reviewed_at: chrono::DateTime::UNIX_EPOCH + chrono::Duration::days(day),
+            rating,
+            elapsed_days: 0,
+            previous: None,
+            current: SchedulerMemory::Fsrs { stability: 1.0, difficulty: 5.0 },
+            step: None,
+            time_spent: 0,
+            undone: false,
+        };
+        append_review_log(dir.path(), &answer(0, Difficulty::Good)).await.unwrap();
+        append_review_log(dir.path(), &answer(1, Difficulty::Again)).await.unwrap();
+        let written = std::fs::read_to_string(dir.path().join(REVIEW_LOG_FILE)).unwrap();
+        append_review_log(dir.path(), &ReviewLogEntry { undone: true, ..answer(1, Difficulty::Again) }).await.unwrap();
+
+        // earlier lines are kept as they were
+        assert!(std::fs::read_to_string(dir.path().join(REVIEW_LOG_FILE)).unwrap().starts_with(&written));
+        assert_eq!(read_review_log(dir.path()).await.unwrap(), vec![answer(0, Difficulty::Good)]);
+    }
+
+    #[tokio::test]
+    async fn test_renames_keep_the_review_log() {
+        use crate::core::learning::Difficulty;
+        use crate::repository::lock::SchedulerMemory;
+        use crate::repository::review_log::ReviewLogEntry;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("deck.yaml"), "name: test\ncard_files: [cards.yml]\n").unwrap();
+        std::fs::write(dir.path().join("cards.yml"), "- name: gato\n  content: cat\n").unwrap();
+        let deck = read_deck_meta_file(dir.path()).await.unwrap();
+        let old_id = create_or_update_lock_file(dir.path(), &deck).await.unwrap().order[0];
+        let answer = |day: i64| ReviewLogEntry {
+            name: old_id.0,
+            content: old_id.1,
+            reviewed_at: chrono::DateTime::UNIX_EPOCH + chrono::Duration::days(day),
+            rating: Difficulty::Good,
+            elapsed_days: 0,
+            previous: None,
+            current: SchedulerMemory::Fsrs { stability: 1.0, difficulty: 5.0 },
+            step: None,
+            time_spent: 0,
+            undone: false,
+        };
+        append_review_log(dir.path(), &answer(0)).await.unwrap();
+        append_review_log(dir.path(), &answer(1)).await.unwrap();
+        append_review_log(dir.path(), &ReviewLogEntry { undone: true, ..answer(1) }).await.unwrap();
+        let written = std::fs::read_to_string(dir.path().join(REVIEW_LOG_FILE)).unwrap();
+
+        std::fs::write(dir.path().join("cards.yml"), "- name: el gato\n  content: cat\n").unwrap();
+        let loaded = create_or_update_lock_file(dir.path(), &deck).await.unwrap();
+        assert_eq!(loaded.migrations.len(), 1);
+        let new_id = loaded.order[0];
+
+        let log = std::fs::read_to_string(dir.path().join(REVIEW_LOG_FILE)).unwrap();
+        assert!(log.starts_with(&written));
+        assert_eq!(log.lines().count(), 4);
+        let entries = read_review_log(dir.path()).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_id(), new_id);
+        assert_eq!(entries[0].reviewed_at, answer(0).reviewed_at);
+    }
+
+    #[tokio::test]
+    async fn test_sub_deck_inherits_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let child = dir.path().join("japanese");
+        std::fs::create_dir_all(&child).unwrap();
+        std::fs::write(dir.path().join("deck.yaml"), "name: Language\ncard_files: ['.']\nnew_per_day: 5\nfsrs_option:\n  retention: 0.9\n").unwrap();
+        std::fs::write(child.join("deck.yaml"), "name: Japanese\ncard_files: [cards.yml]\nreviews_per_day: 50\n").unwrap();
+        std::fs::write(child.join("cards.yml"), "[]").unwrap();
+
+        let mut deck = read_deck_meta_file(&child).await.unwrap();
+        assert_eq!(deck.new_per_day, 5);
+        assert_eq!(deck.reviews_per_day, 50);
+        assert_eq!(deck.fsrs_option.as_ref().unwrap().retention, 0.9);
+
+        deck.new_per_day = 10;
+        write_deck_meta_file(&child, &deck).await.unwrap();
+        let written = std::fs::read_to_string(child.join("deck.yaml")).unwrap();
+        assert!(written.contains("new_per_day: 10"));
+        assert!(!written.contains("fsrs_option"));
+
+        let parent = read_deck_meta_file(dir.path()).await.unwrap();
+        assert!(resolve_card_files(dir.path(), &parent).await.unwrap().is_empty());
+    }
+}
@@ -1,9 +1,10 @@
-use crate::repository::lock::{LockItem, MemoryState};
-use chrono::{Duration, Local, TimeZone, Utc};
-use fsrs::FSRS;
+use crate::core::scheduler::Scheduler;
+use crate::repository::deck::Deck;
+use crate::repository::lock::{LearningPhase, LockItem, MemoryState, SchedulerMemory};
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub enum Difficulty {
     Easy,
     Good,
@@ -11,44 +12,315 @@ pub enum Difficulty {
     Again,
 }
 
+impl Difficulty {
+    /// All answers, in the order of their keys from `f` to `a`.
+    pub const ALL: [Difficulty; 4] = [Difficulty::Again, Difficulty::Hard, Difficulty::Good, Difficulty::Easy];
+    /// The key answering with this difficulty during a review session.
+    pub fn key(&self) -> char {
+        match self {
+            Difficulty::Easy => 'a',
+            Difficulty::Good => 's',
+            Difficulty::Hard => 'd',
+            Difficulty::Again => 'f',
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Good => "good",
+            Difficulty::Hard => "hard",
+            Difficulty::Again => "again",
+        }
+    }
+    pub fn from_rating(rating: u32) -> Option<Difficulty> {
+        match rating {
+            1 => Some(Difficulty::Again),
+            2 => Some(Difficulty::Hard),
+            3 => Some(Difficulty::Good),
+            4 => Some(Difficulty::Easy),
+            _ => None,
+        }
+    }
+    /// The 1-4 rating used by FSRS.
+    pub fn rating(&self) -> u32 {
+        match self {
+            Difficulty::Again => 1,
+            Difficulty::Hard => 2,
+            Difficulty::Good => 3,
+            Difficulty::Easy => 4,
+        }
+    }
+}
+
+/// The study day `time` belongs to in its timezone, where a day begins at `rollover_hour`.
+pub fn study_day<Tz: TimeZone>(time: &DateTime<Tz>, rollover_hour: u32) -> NaiveDate {
+    (time.naive_local() - Duration::hours(rollover_hour as i64)).date()
+}
+
+/// A short form of an interval in days, like `10m`, `3d` or `1.5y`.
+pub fn format_interval(days: f32) -> String {
+    let minutes = days * 24.0 * 60.0;
+    if minutes < 60.0 {
+        format!("{}m", minutes.round().max(1.0))
+    } else if minutes < 24.0 * 60.0 {
+        format!("{}h", (minutes / 60.0).round())
+    } else if days < 30.0 {
+        format!("{}d", days.round())
+    } else if days < 365.0 {
+        format!("{:.1}mo", days / 30.0)
+    } else {
+        format!("{:.1}y", days / 365.0)
+    }
+}
+
+/// Whole study days between the last review and `now`, counted in the timezone of `now`.
+pub fn elapsed_days<Tz: TimeZone>(last_reviewed: &DateTime<Utc>, now: &DateTime<Tz>, rollover_hour: u32) -> u32 {
+    let last_reviewed = last_reviewed.with_timezone(&now.timezone());
+    let days = (study_day(now, rollover_hour) - study_day(&last_reviewed, rollover_hour)).num_days();
+    days.max(0) as u32
+}
+
+/// The learning and relearning steps of a deck, in days.
+#[derive(Debug, Clone, Default)]
+pub struct LearningSteps {
+    pub learning: Vec<f32>,
+    pub relearning: Vec<f32>,
+}
+
+impl LearningSteps {
+    pub fn for_deck(deck: &Deck) -> Self {
+        LearningSteps {
+            learning: deck.learning_steps.iter().map(|step| step.days()).collect(),
+            relearning: deck.relearning_steps.iter().map(|step| step.days()).collect(),
+        }
+    }
+    /// The step a card goes to and its interval, or `None` when it gets a long-term interval.
+    ///
+    /// New cards start at the first learning step and forgotten reviews at the first relearning step.
+    /// `again` goes back to the first step, `hard` repeats the step, `good` moves on and `easy` graduates.
+    fn next_step(&self, previous: Option<&MemoryState>, difficulty: Difficulty) -> Option<(LearningPhase, f32)> {
+        let (relearning, current) = match previous.map(|state| state.step) {
+            None => (false, 0),
+            Some(Some(LearningPhase::Learning(step))) => (false, step),
+            Some(Some(LearningPhase::Relearning(step))) => (true, step),
+            Some(None) if difficulty == Difficulty::Again => (true, 0),
+            Some(None) => return None,
+        };
+        let next = match difficulty {
+            Difficulty::Again => 0,
+            Difficulty::Hard => current,
+            Difficulty::Good => current + 1,
+            Difficulty::Easy => return None,
+        };
+        let steps = if relearning { &self.relearning } else { &self.learning };
+        let interval = *steps.get(next as usize)?;
+        let phase = if relearning { LearningPhase::Relearning(next) } else { LearningPhase::Learning(next) };
+        Some((phase, interval))
+    }
+}
+
 impl LockItem {
     pub fn set_ignored(&mut self, ignored: bool) {
         self.ignored = ignored;
     }
-    pub fn next_state(&mut self, fsrs: &FSRS, retention: f32, difficulty: Difficulty) {
-        let last_reviewed = Utc::now().naive_utc();
-        let new_state = match self.state {
-            None => fsrs.next_states(None, retention, 0),
-            Some(ref state) => {
-                let last_review_date = Local.from_local_datetime(&last_reviewed).unwrap();
-                let just_now = Local::now();
-                let interval = (just_now - last_review_date).num_days();
-                fsrs.next_states(Some(state.into()), retention, interval as u32)
-            }
-        }.unwrap();
-        let new_state = match difficulty {
-            Difficulty::Easy => new_state.easy,
-            Difficulty::Good => new_state.good,
-            Difficulty::Hard => new_state.hard,
-            Difficulty::Again => new_state.again,
+    /// Apply an answer given at `now` and return the elapsed days passed to the scheduler.
+    pub fn next_state<Tz: TimeZone>(
+        &mut self,
+        scheduler: &dyn Scheduler,
+        steps: &LearningSteps,
+        difficulty: Difficulty,
+        now: &DateTime<Tz>,
+        rollover_hour: u32,
+    ) -> u32 {
+        let elapsed_days = self.elapsed_days(now, rollover_hour);
+        let (memory, interval, step) = self.answer(scheduler, steps, difficulty, elapsed_days);
+        self.state = Some(MemoryState {
+            last_reviewed: now.with_timezone(&Utc),
+            interval,
+            memory,
+            step,
+        });
+        elapsed_days
+    }
+    /// The memory, interval and learning step after an answer.
+    fn answer(&self, scheduler: &dyn Scheduler, steps: &LearningSteps, difficulty: Difficulty, elapsed_days: u32)
+        -> (SchedulerMemory, f32, Option<LearningPhase>) {
+        let previous = self.state.as_ref();
+        let next_step = steps.next_step(previous, difficulty);
+        let (memory, interval) = match (previous.and_then(|state| state.step), next_step) {
+            // the memory the card got when entering its steps is kept until it graduates
+            (Some(_), Some((_, interval))) => (previous.unwrap().memory, interval),
+            // new cards graduate as if answered for the first time
+            (Some(LearningPhase::Learning(_)), None) => scheduler.next(None, difficulty, 0),
+            (None, Some((_, interval))) => (scheduler.next(previous, difficulty, elapsed_days).0, interval),
+            (_, None) => scheduler.next(previous, difficulty, elapsed_days),
         };
-        let new_memory_state = MemoryState {
-            last_reviewed,
-            interval: new_state.interval,
-            stability: new_state.memory.stability,
-            difficulty: new_state.memory.difficulty,
+        (memory, interval, next_step.map(|(phase, _)| phase))
+    }
+    /// Whether the card is going through learning or relearning steps.
+    pub fn is_learning(&self) -> bool {
+        self.state.as_ref().is_some_and(|state| state.step.is_some())
+    }
+    fn elapsed_days<Tz: TimeZone>(&self, now: &DateTime<Tz>, rollover_hour: u32) -> u32 {
+        self.state.as_ref().map_or(0, |state| elapsed_days(&state.last_reviewed, now, rollover_hour))
+    }
+    /// The interval in days each answer given at `now` would schedule, leaving the state unchanged.
+    pub fn preview_intervals<Tz: TimeZone>(
+        &self,
+        scheduler: &dyn Scheduler,
+        steps: &LearningSteps,
+        now: &DateTime<Tz>,
+        rollover_hour: u32,
+    ) -> [(Difficulty, f32); 4] {
+        let elapsed_days = self.elapsed_days(now, rollover_hour);
+        Difficulty::ALL.map(|difficulty| {
+            (difficulty, self.answer(scheduler, steps, difficulty, elapsed_days).1)
+        })
+    }
+    /// When the card should be reviewed again in `timezone`. New cards have no due time.
+    ///
+    /// Intervals of a day or more end at the start of a study day, so that a card reviewed in the
+    /// evening is due with the other cards of its day; shorter learning steps end at their exact time.
+    pub fn next_review<Tz: TimeZone>(&self, timezone: &Tz, rollover_hour: u32) -> Option<DateTime<Utc>> {
+        let state = self.state.as_ref()?;
+        if state.interval < 1.0 {
+            let interval = Duration::seconds((state.interval * 24.0 * 60.0 * 60.0) as i64);
+            return Some(state.last_reviewed + interval);
+        }
+        let last_reviewed = state.last_reviewed.with_timezone(timezone);
+        let due_day = study_day(&last_reviewed, rollover_hour) + Duration::days(state.interval.round() as i64);
+        let day_start = due_day.and_hms_opt(rollover_hour, 0, 0)?;
+        // a rollover skipped by a daylight saving change falls back to the same wall time in UTC
+        let due = timezone.from_local_datetime(&day_start).earliest()
+            .map_or_else(|| day_start.and_utc(), |due| due.with_timezone(&Utc));
+        Some(due)
+    }
+    /// Whether the card is due at `now`. New cards are always due.
+    pub fn filter_on_time<Tz: TimeZone>(&self, now: &DateTime<Tz>, rollover_hour: u32) -> bool {
+        match self.next_review(&now.timezone(), rollover_hour) {
+            None => true,
+            Some(next_review) => *now >= next_review,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
+    use uuid::Uuid;
+    use crate::core::learning::{elapsed_days, format_interval, Difficulty, LearningSteps};
+    use crate::core::scheduler::for_deck;
+    use crate::repository::deck::{Deck, LearningStep};
+    use crate::repository::lock::{LearningPhase, LockItem, MemoryState, SchedulerMemory};
+
+    fn reviewed_item(last_reviewed: DateTime<Utc>, interval: f32) -> LockItem {
+        let id = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"card");
+        LockItem {
+            name: id,
+            content: id,
+            state: Some(MemoryState {
+                last_reviewed,
+                interval,
+                memory: SchedulerMemory::Fsrs { stability: interval, difficulty: 5.0 },
+                step: None,
+            }),
+            ignored: false,
+            content_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_elapsed_days_with_rollover() {
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        // 2024-01-01 23:00 in Tokyo
+        let last_reviewed = Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap();
+        let same_night = tokyo.with_ymd_and_hms(2024, 1, 2, 3, 0, 0).unwrap();
+        let next_morning = tokyo.with_ymd_and_hms(2024, 1, 2, 5, 0, 0).unwrap();
+        assert_eq!(elapsed_days(&last_reviewed, &same_night, 4), 0);
+        assert_eq!(elapsed_days(&last_reviewed, &next_morning, 4), 1);
+        assert_eq!(elapsed_days(&last_reviewed, &same_night, 0), 1);
+    }
+
+    #[test]
+    fn test_reviews_are_due_at_study_day_start() {
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let next_rollover = tokyo.with_ymd_and_hms(2024, 1, 2, 4, 0, 0).unwrap();
+        // reviewed at 23:00 and 03:00 in Tokyo, both on the study day of January 1st
+        for last_reviewed in [Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap(), Utc.with_ymd_and_hms(2024, 1, 1, 18, 0, 0).unwrap()] {
+            let item = reviewed_item(last_reviewed, 1.0);
+            assert_eq!(item.next_review(&tokyo, 4), Some(next_rollover.with_timezone(&Utc)));
+            assert!(!item.filter_on_time(&(next_rollover - Duration::minutes(1)), 4));
+            assert!(item.filter_on_time(&next_rollover, 4));
+        }
+        // learning steps shorter than a day keep their exact time
+        let last_reviewed = Utc.with_ymd_and_hms(2024, 1, 1, 14, 0, 0).unwrap();
+        let item = reviewed_item(last_reviewed, 10.0 / (24.0 * 60.0));
+        assert_eq!(item.next_review(&tokyo, 4), Some(last_reviewed + Duration::minutes(10)));
+    }
+
+    #[test]
+    fn test_preview_intervals_match_answers() {
+        let scheduler = for_deck(&Deck::new("deck".to_string(), Vec::new())).unwrap();
+        let item = reviewed_item(Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap(), 3.0);
+        let now = Utc.with_ymd_and_hms(2024, 1, 5, 12, 0, 0).unwrap();
+        let preview = item.preview_intervals(scheduler.as_ref(), &LearningSteps::default(), &now, 4);
+        assert_eq!(item, reviewed_item(Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap(), 3.0));
+        for (difficulty, interval) in preview {
+            let mut answered = item.clone();
+            answered.next_state(scheduler.as_ref(), &LearningSteps::default(), difficulty, &now, 4);
+            assert_eq!(answered.state.unwrap().interval, interval);
+        }
+        assert!(preview[0].1 < preview[3].1);
+        let formatted: Vec<_> = [0.007, 0.25, 3.2, 45.0, 500.0].map(format_interval).into();
+        assert_eq!(formatted, vec!["10m", "6h", "3d", "1.5mo", "1.4y"]);
+    }
+
+    #[test]
+    fn test_learning_steps() {
+        let mut deck = Deck::new("deck".to_string(), Vec::new());
+        deck.learning_steps = ["1m", "10m"].map(|step| LearningStep::try_from(step.to_string()).unwrap()).into();
+        deck.relearning_steps = vec![LearningStep::try_from("10m".to_string()).unwrap()];
+        assert_eq!(String::from(LearningStep::try_from("120m".to_string()).unwrap()), "2h");
+        assert!(LearningStep::try_from("0m".to_string()).is_err());
+        assert!(LearningStep::try_from("5x".to_string()).is_err());
+
+        let scheduler = for_deck(&deck).unwrap();
+        let steps = LearningSteps::for_deck(&deck);
+        let id = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"card");
+        let mut item = LockItem { name: id, content: id, state: None, ignored: false, content_hash: None };
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let mut answer = |difficulty| {
+            item.next_state(scheduler.as_ref(), &steps, difficulty, &now, 4);
+            let state = item.state.clone().unwrap();
+            (state.step, format_interval(state.interval))
         };
-        self.state = Some(new_memory_state);
+        assert_eq!(answer(Difficulty::Again), (Some(LearningPhase::Learning(0)), "1m".to_string()));
+        assert_eq!(answer(Difficulty::Good), (Some(LearningPhase::Learning(1)), "10m".to_string()));
+        assert_eq!(answer(Difficulty::Hard), (Some(LearningPhase::Learning(1)), "10m".to_string()));
+        let (step, _) = answer(Difficulty::Good);
+        assert_eq!(step, None);
+        assert_eq!(answer(Difficulty::Again), (Some(LearningPhase::Relearning(0)), "10m".to_string()));
+        let (step, _) = answer(Difficulty::Good);
+        assert_eq!(step, None);
     }
-    pub fn filter_on_time(&self) -> bool {
-        let now = Utc::now().naive_utc();
-        if self.state.is_none() {
-            return true;
+
+    #[test]
+    fn test_next_state_elapsed_days() {
+        let scheduler = for_deck(&Deck::new("deck".to_string(), Vec::new())).unwrap();
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        let last_reviewed = Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap();
+        let mut intervals = Vec::new();
+        for (day, expected_elapsed) in [(2, 1), (4, 3), (11, 10)] {
+            let mut item = reviewed_item(last_reviewed, 3.0);
+            let now = tokyo.with_ymd_and_hms(2024, 1, day, 12, 0, 0).unwrap();
+            let elapsed = item.next_state(scheduler.as_ref(), &LearningSteps::default(), Difficulty::Good, &now, 4);
+            assert_eq!(elapsed, expected_elapsed);
+            let state = item.state.unwrap();
+            assert_eq!(state.last_reviewed, now.with_timezone(&Utc));
+            intervals.push(state.interval);
         }
-        let state = self.state.as_ref().unwrap();
-        let last_reviewed = state.last_reviewed;
-        let interval = Duration::seconds((state.interval * 24.0 * 60.0 * 60.0) as i64);
-        let next_review = last_reviewed + interval;
-        now >= next_review
+        // an early review strengthens memory less than an on-time one, a late one more
+        assert!(intervals[0] < intervals[1]);
+        assert!(intervals[1] < intervals[2]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,129 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use anyhow::{bail, Result};
+use crate::core::deck_loader::{read_lock_file, write_lock_file, LoadedCards};
+use crate::repository::deck::CardItem;
+use crate::repository::lock::{CardItemIdentify, LockItem};
+
+/// Selects cards by name, tag or id. A card matches when any of the criteria matches.
+#[derive(Debug, Clone, Default)]
+pub struct CardSelector {
+    pub names: Vec<String>,
+    pub tags: Vec<String>,
+    /// Explicit card ids, or a prefix of the id shown by `mem-yaml list`.
+    pub ids: Vec<String>,
+}
+
+/// Shortest prefix of a shown id accepted by `--id`, so that a short prefix cannot select many cards by accident.
+const MIN_ID_PREFIX: usize = 8;
+
+impl CardSelector {
+    /// The ids of the selected cards.
+    ///
+    /// A selected id equal to the explicit `id` of a card selects that card. Otherwise it is a prefix of
+    /// the id shown by `mem-yaml list`, which must be at least `MIN_ID_PREFIX` characters long and match
+    /// a single card.
+    pub fn select(&self, cards: &HashMap<CardItemIdentify, CardItem>) -> Result<HashSet<CardItemIdentify>> {
+        let mut selected: HashSet<_> = cards.iter()
+            .filter(|(_, card)| {
+                self.names.contains(&card.name)
+                    || card.tags.as_ref().is_some_and(|tags| tags.iter().any(|tag| self.tags.contains(tag)))
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for selected_id in &self.ids {
+            let explicit: Vec<_> = cards.iter()
+                .filter(|(_, card)| card.id.as_ref() == Some(selected_id))
+                .map(|(id, _)| *id)
+                .collect();
+            if !explicit.is_empty() {
+                selected.extend(explicit);
+                continue;
+            }
+            if selected_id.len() < MIN_ID_PREFIX {
+                bail!("Id prefix {} is too short, use at least {} characters", selected_id, MIN_ID_PREFIX);
+            }
+            let matching: Vec<_> = cards.keys().filter(|id| id.to_string().starts_with(selected_id.as_str())).collect();
+            if matching.len() > 1 {
+                bail!("Ambiguous id {}: it matches {} cards", selected_id, matching.len());
+            }
+            selected.extend(matching);
+        }
+        Ok(selected)
+    }
+}
+
+/// Set the `ignored` flag of the selected cards and return the names of the changed cards.
+pub async fn set_suspended(dir: &Path, cards: &LoadedCards, selector: &CardSelector, suspended: bool) -> Result<Vec<String>> {
+    let selected = selector.select(&cards.cards)?;
+    let mut lock_list = read_lock_file(dir).await?;
+    let mut changed = Vec::new();
+    for item in lock_list.iter_mut() {
+        let id = item.get_id();
+        let Some(card) = cards.cards.get(&id) else {
+            continue;
+        };
+        if item.ignored != suspended && selected.contains(&id) {
+            item.set_ignored(suspended);
+            changed.push(card.name.clone());
+        }
+    }
+    if !changed.is_empty() {
+        write_lock_file(dir, &lock_list).await?;
+    }
+    Ok(changed)
+}
+
+/// The cards of the deck in file order, together with their lock item.
+pub async fn list_cards<'a>(dir: &Path, cards: &'a LoadedCards) -> Result<Vec<(&'a CardItem, LockItem)>> {
+    let mut lock_list: HashMap<_, _> = read_lock_file(dir).await?
+        .into_iter().map(|item| (item.get_id(), item)).collect();
+    Ok(cards.order.iter().filter_map(|id| {
+        let item = lock_list.remove(id)?;
+        Some((&cards.cards[id], item))
+    }).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use uuid::Uuid;
+    use crate::repository::deck::{CardItem, CardVariant};
+    use crate::repository::lock::CardItemIdentify;
+    use super::CardSelector;
+
+    fn card(id: Option<&str>, name: &str) -> CardItem {
+        CardItem {
+            id: id.map(str::to_string),
+            name: name.to_string(),
+            glance: None,
+            content: String::new(),
+            tags: None,
+            direction: None,
+            card_type: None,
+            variant: CardVariant::Forward,
+        }
+    }
+
+    #[test]
+    fn test_select_by_id_prefix() {
+        let shown = |id: &str| CardItemIdentify(Uuid::nil(), Uuid::parse_str(id).unwrap());
+        let first = shown("0123abcd-0000-0000-0000-000000000001");
+        let second = shown("0123abcd-0000-0000-0000-000000000002");
+        let third = shown("4567abcd-0000-0000-0000-000000000003");
+        let cards = HashMap::from([
+            (first, card(None, "first")),
+            (second, card(None, "second")),
+            (third, card(Some("0123"), "third")),
+        ]);
+        let select = |id: &str| CardSelector { ids: vec![id.to_string()], ..Default::default() }.select(&cards);
+
+        assert_eq!(select("4567abcd").unwrap(), HashSet::from([third]));
+        assert_eq!(select("0123abcd-0000-0000-0000-000000000002").unwrap(), HashSet::from([second]));
+        // an explicit id is selected even when it is short or prefixes other cards
+        assert_eq!(select("0123").unwrap(), HashSet::from([third]));
+        assert!(select("4567").unwrap_err().to_string().contains("too short"));
+        assert!(select("").is_err());
+        assert!(select("0123abcd").unwrap_err().to_string().contains("Ambiguous id"));
+    }
+}
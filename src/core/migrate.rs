@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::Result;
+use chrono::Local;
+use crate::core::deck_loader::{read_deck_meta_file, read_lock_file, read_review_log, update_deck_meta_key, write_lock_file};
+use crate::core::learning::LearningSteps;
+use crate::core::scheduler::{self, Scheduler};
+use crate::repository::deck::{Deck, RepeatAlgorithm};
+use crate::repository::lock::{CardItemIdentify, LockItem};
+use crate::repository::review_log::ReviewLogEntry;
+
+pub struct AlgorithmMigrationReport {
+    /// Cards whose memory was rebuilt by replaying their review history.
+    pub replayed: usize,
+    /// Cards whose memory was converted from the one of the previous algorithm.
+    pub converted: usize,
+}
+
+/// Replay a complete review history of a card, or `None` when the log does not lead to its current state.
+fn replay(item: &LockItem, history: &[ReviewLogEntry], scheduler: &dyn Scheduler, deck: &Deck) -> Option<LockItem> {
+    let state = item.state.as_ref()?;
+    // histories starting with a review, like imported ones, miss the first answers
+    if history.first()?.previous.is_some() {
+        return None;
+    }
+    let steps = LearningSteps::for_deck(deck);
+    let mut replayed = LockItem { state: None, ..item.clone() };
+    for entry in history {
+        let reviewed_at = entry.reviewed_at.with_timezone(&Local);
+        replayed.next_state(scheduler, &steps, entry.rating, &reviewed_at, deck.day_rollover_hour);
+    }
+    let replayed_state = replayed.state.as_ref()?;
+    (replayed_state.last_reviewed == state.last_reviewed).then_some(replayed)
+}
+
+/// Switch the deck in `dir` to the `to` algorithm, keeping the progress of reviewed cards.
+///
+/// Cards are replayed through the new algorithm from the review log when their whole history is
+/// logged, and otherwise have their memory converted, keeping the last review and interval.
+pub async fn migrate_algorithm(dir: &Path, to: RepeatAlgorithm) -> Result<AlgorithmMigrationReport> {
+    let mut deck = read_deck_meta_file(dir).await?;
+    deck.algorithm = to;
+    let scheduler = scheduler::for_deck(&deck)?;
+    let mut histories: HashMap<CardItemIdentify, Vec<ReviewLogEntry>> = HashMap::new();
+    for entry in read_review_log(dir).await? {
+        histories.entry(entry.get_id()).or_default().push(entry);
+    }
+    let mut report = AlgorithmMigrationReport { replayed: 0, converted: 0 };
+    let mut lock_list = read_lock_file(dir).await?;
+    for item in lock_list.iter_mut() {
+        if item.state.is_none() {
+            continue;
+        }
+        let history = histories.get(&item.get_id()).map_or(&[][..], Vec::as_slice);
+        match replay(item, history, scheduler.as_ref(), &deck) {
+            Some(replayed) => {
+                *item = replayed;
+                report.replayed += 1;
+            }
+            None => {
+                let state = item.state.as_mut().unwrap();
+                state.memory = scheduler.convert(state);
+                report.converted += 1;
+            }
+        }
+    }
+    write_lock_file(dir, &lock_list).await?;
+    update_deck_meta_key(dir, "algorithm", &deck.algorithm).await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Local, TimeZone, Utc};
+    use uuid::Uuid;
+    use crate::core::learning::{Difficulty, LearningSteps};
+    use crate::core::scheduler::for_deck;
+    use crate::repository::deck::{Deck, RepeatAlgorithm};
+    use crate::repository::lock::{LockItem, SchedulerMemory};
+    use crate::repository::review_log::ReviewLogEntry;
+    use super::replay;
+
+    #[test]
+    fn test_replay_history_with_new_algorithm() {
+        let id = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"card");
+        let mut fsrs_deck = Deck::new("deck".to_string(), Vec::new());
+        let fsrs = for_deck(&fsrs_deck).unwrap();
+        let mut item = LockItem { name: id, content: id, state: None, ignored: false, content_hash: None };
+        let mut history = Vec::new();
+        let first = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        for (day, rating) in [(0, Difficulty::Good), (3, Difficulty::Good), (12, Difficulty::Again)] {
+            let previous = item.clone();
+            let reviewed_at = (first + Duration::days(day)).with_timezone(&Local);
+            let elapsed_days = item.next_state(fsrs.as_ref(), &LearningSteps::default(), rating, &reviewed_at, fsrs_deck.day_rollover_hour);
+            history.push(ReviewLogEntry::new(&previous, &item, rating, elapsed_days, std::time::Duration::ZERO));
+        }
+
+        fsrs_deck.algorithm = RepeatAlgorithm::Sm2;
+        let sm2 = for_deck(&fsrs_deck).unwrap();
+        let replayed = replay(&item, &history, sm2.as_ref(), &fsrs_deck).unwrap();
+        let state = replayed.state.unwrap();
+        assert_eq!(state.last_reviewed, item.state.as_ref().unwrap().last_reviewed);
+        assert_eq!(state.interval, 1.0);
+        assert!(matches!(state.memory, SchedulerMemory::Sm2 { repetitions: 0, .. }));
+
+        // a history missing its first answers is converted instead
+        assert!(replay(&item, &history[1..], sm2.as_ref(), &fsrs_deck).is_none());
+    }
+}
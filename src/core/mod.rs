@@ -1,62 +1,163 @@
 use std::collections::HashMap;
-use fsrs::FSRS;
+use chrono::Local;
 use rand::prelude::SliceRandom;
-use crate::core::deck_loader::write_lock_file;
+use std::time::Duration;
+use crate::core::deck_loader::{append_review_log, write_daily_counter, write_lock_file, LoadedCards};
 use anyhow::Result;
-use crate::repository::deck::{CardItem, Deck};
+use crate::repository::daily_counter::DailyCounter;
+use crate::repository::deck::{CardItem, Deck, NewCardOrder, TypedAnswerOption};
 use crate::repository::lock::{CardItemIdentify, LockItem};
+use crate::repository::review_log::ReviewLogEntry;
 
 pub mod deck_loader;
 pub mod learning;
+pub mod manage;
 pub mod deck_initial;
+pub mod optimizer;
+pub mod queue;
+pub mod stats;
+pub mod tag_filter;
+pub mod check;
+pub mod collection;
+pub mod typed_answer;
+pub mod scheduler;
+pub mod migrate;
 
 pub struct RunningCore {
     pub working_dir: std::path::PathBuf,
     deck: Deck,
-    fsrs: FSRS,
+    scheduler: Box<dyn scheduler::Scheduler>,
+    steps: learning::LearningSteps,
     pub(crate) cards: HashMap<CardItemIdentify, CardItem>,
-    lock_file: HashMap<CardItemIdentify, LockItem>
+    lock_file: HashMap<CardItemIdentify, LockItem>,
+    order: Vec<CardItemIdentify>,
+    files: HashMap<CardItemIdentify, String>,
+    siblings: HashMap<CardItemIdentify, Vec<CardItemIdentify>>,
+    counter: DailyCounter,
+    done: usize,
+    undo_stack: Vec<UndoEntry>
+}
+
+/// How many answers can be undone during a session.
+pub(crate) const UNDO_LIMIT: usize = 20;
+
+/// What is needed to revert one answer or ignore.
+struct UndoEntry {
+    previous: LockItem,
+    counter: DailyCounter,
+    /// The answer appended to the review log, none for ignored cards.
+    logged: Option<ReviewLogEntry>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Progress {
+    pub due: usize,
+    pub new: usize,
+    pub done: usize,
 }
 
 impl RunningCore {
-    pub async fn new(working_dir: std::path::PathBuf, deck: Deck, cards: HashMap<CardItemIdentify, CardItem>) -> Self {
-        let lock_file = deck_loader::read_lock_file(&working_dir).await.unwrap();
+    pub async fn new(working_dir: std::path::PathBuf, deck: Deck, cards: LoadedCards) -> Result<Self> {
+        let lock_file = deck_loader::read_lock_file(&working_dir).await?;
         let lock_file = lock_file.into_iter().map(|item| (item.get_id(), item)).collect();
-        let fsrs = FSRS::new(Some(&[])).unwrap();
-        RunningCore {
+        let scheduler = scheduler::for_deck(&deck)?;
+        let steps = learning::LearningSteps::for_deck(&deck);
+        let today = learning::study_day(&Local::now(), deck.day_rollover_hour);
+        let counter = deck_loader::read_daily_counter(&working_dir).await?
+            .unwrap_or_else(|| DailyCounter::new(today))
+            .for_day(today);
+        let mut order = cards.order;
+        if deck.new_card_order == NewCardOrder::Random {
+            order.shuffle(&mut rand::thread_rng());
+        }
+        Ok(RunningCore {
             working_dir,
             lock_file,
-            fsrs,
-            cards,
-            deck
-        }
+            scheduler,
+            steps,
+            cards: cards.cards,
+            deck,
+            order,
+            files: cards.files,
+            siblings: cards.siblings,
+            counter,
+            done: 0,
+            undo_stack: Vec::new()
+        })
     }
-    pub fn random_on_time(&self) -> Option<LockItem> {
-        let on_time: Vec<_> = self
-            .lock_file
-            .iter()
-            .filter(|(_,s)| s.filter_on_time())
-            .collect();
-        if on_time.is_empty() {
-            None
-        } else {
-            Some(on_time.choose(&mut rand::thread_rng()).unwrap().1.to_owned())
-        }
+    /// Only study the cards matching `filter` in this session.
+    pub fn restrict(&mut self, filter: &tag_filter::StudyFilter) {
+        let files = &self.files;
+        let dir = &self.working_dir;
+        self.cards.retain(|id, card| {
+            files.get(id).is_some_and(|file| filter.matches(card, dir, file))
+        });
+        self.order.retain(|id| self.cards.contains_key(id));
     }
-    pub async fn next_state(&mut self, id: CardItemIdentify, difficulty: learning::Difficulty) -> Result<()> {
-        let retention = match self.deck.fsrs_option {
-            None => crate::repository::deck::default_retention(),
-            Some(ref fsrs_option) => fsrs_option.retention
-        };
+    pub fn deck_name(&self) -> &str {
+        &self.deck.name
+    }
+    pub fn typed_answer(&self) -> Option<&TypedAnswerOption> {
+        self.deck.typed_answer.as_ref()
+    }
+    /// The interval each answer would schedule the card for.
+    pub fn preview_intervals(&self, id: CardItemIdentify) -> [(learning::Difficulty, f32); 4] {
+        self.lock_file[&id].preview_intervals(self.scheduler.as_ref(), &self.steps, &Local::now(), self.deck.day_rollover_hour)
+    }
+    pub fn progress(&self) -> Progress {
+        let (due, new) = self.queue_sizes();
+        Progress { due, new, done: self.done }
+    }
+    pub async fn next_state(&mut self, id: CardItemIdentify, difficulty: learning::Difficulty, time_spent: Duration) -> Result<()> {
         let lock_item = self.lock_file.get_mut(&id).unwrap();
-        lock_item.next_state(&self.fsrs, retention, difficulty);
+        let previous = lock_item.clone();
+        let counter = self.counter;
+        let now = Local::now();
+        let elapsed_days = lock_item.next_state(self.scheduler.as_ref(), &self.steps, difficulty, &now, self.deck.day_rollover_hour);
+        let entry = ReviewLogEntry::new(&previous, lock_item, difficulty, elapsed_days, time_spent);
+        self.done += 1;
+        self.counter = self.counter.for_day(learning::study_day(&now, self.deck.day_rollover_hour));
+        // answers within learning steps are not limited, so they are not counted either
+        if previous.state.is_none() {
+            self.counter.new += 1;
+        } else if !previous.is_learning() {
+            self.counter.reviews += 1;
+        }
         write_lock_file(&self.working_dir, &self.lock_file.values().cloned().collect()).await?;
+        append_review_log(&self.working_dir, &entry).await?;
+        write_daily_counter(&self.working_dir, &self.counter).await?;
+        self.push_undo(UndoEntry { previous, counter, logged: Some(entry) });
         Ok(())
     }
     pub async fn set_ignored(&mut self, id: CardItemIdentify) -> Result<()> {
         let lock_item = self.lock_file.get_mut(&id).unwrap();
+        let undo_entry = UndoEntry { previous: lock_item.clone(), counter: self.counter, logged: None };
         lock_item.set_ignored(true);
+        self.done += 1;
         write_lock_file(&self.working_dir, &self.lock_file.values().cloned().collect()).await?;
+        self.push_undo(undo_entry);
         Ok(())
     }
+    fn push_undo(&mut self, entry: UndoEntry) {
+        if self.undo_stack.len() == UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(entry);
+    }
+    /// Revert the last answer or ignore and return the card so that it can be shown again.
+    pub async fn undo(&mut self) -> Result<Option<CardItemIdentify>> {
+        let Some(entry) = self.undo_stack.pop() else {
+            return Ok(None);
+        };
+        let id = entry.previous.get_id();
+        self.lock_file.insert(id, entry.previous);
+        self.done -= 1;
+        write_lock_file(&self.working_dir, &self.lock_file.values().cloned().collect()).await?;
+        if let Some(logged) = entry.logged {
+            self.counter = entry.counter;
+            write_daily_counter(&self.working_dir, &self.counter).await?;
+            append_review_log(&self.working_dir, &ReviewLogEntry { undone: true, ..logged }).await?;
+        }
+        Ok(Some(id))
+    }
 }
\ No newline at end of file
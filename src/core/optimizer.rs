@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::Result;
+use fsrs::{FSRSItem, FSRSReview, FSRS};
+use crate::core::deck_loader::{read_deck_meta_file, read_review_log, update_deck_meta_key};
+use crate::repository::deck::{default_retention, FsrsOption};
+use crate::repository::lock::CardItemIdentify;
+use crate::repository::review_log::ReviewLogEntry;
+
+/// Turn the review log into FSRS training items.
+///
+/// Every card contributes one item per review after its first one, each holding the history up
+/// to that review. Cards whose first logged answer was not given on a new card are skipped,
+/// because their history is truncated and would mislead the optimizer.
+pub fn build_training_set(entries: &[ReviewLogEntry]) -> Vec<FSRSItem> {
+    let mut histories: HashMap<CardItemIdentify, Vec<&ReviewLogEntry>> = HashMap::new();
+    for entry in entries {
+        histories.entry(entry.get_id()).or_default().push(entry);
+    }
+    let mut items = Vec::new();
+    for mut history in histories.into_values() {
+        history.sort_by_key(|entry| entry.reviewed_at);
+        if history.first().is_none_or(|entry| entry.previous.is_some()) {
+            continue;
+        }
+        let reviews: Vec<_> = history.iter().enumerate().map(|(index, entry)| FSRSReview {
+            rating: entry.rating.rating(),
+            delta_t: if index == 0 { 0 } else { entry.elapsed_days },
+        }).collect();
+        for end in 2..=reviews.len() {
+            items.push(FSRSItem { reviews: reviews[..end].to_vec() });
+        }
+    }
+    items
+}
+
+pub struct OptimizeReport {
+    pub items: usize,
+    pub weights: Vec<f32>,
+}
+
+/// Train FSRS weights from the review log of the deck in `dir` and store them in its metadata.
+pub async fn optimize(dir: &Path) -> Result<OptimizeReport> {
+    let mut deck = read_deck_meta_file(dir).await?;
+    let entries = read_review_log(dir).await?;
+    let items = build_training_set(&entries);
+    let item_count = items.len();
+    if item_count == 0 {
+        anyhow::bail!("Not enough review history to optimize");
+    }
+    let weights = tokio::task::spawn_blocking(move || {
+        FSRS::new(None)?.compute_parameters(items, None)
+    }).await??;
+    match deck.fsrs_option {
+        None => deck.fsrs_option = Some(FsrsOption {
+            retention: default_retention(),
+            weights: Some(weights.clone()),
+        }),
+        Some(ref mut option) => option.weights = Some(weights.clone()),
+    }
+    update_deck_meta_key(dir, "fsrs_option", &deck.fsrs_option).await?;
+    Ok(OptimizeReport { items: item_count, weights })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::DateTime;
+    use uuid::Uuid;
+    use crate::core::learning::Difficulty;
+    use crate::repository::lock::SchedulerMemory;
+    use crate::repository::review_log::ReviewLogEntry;
+
+    fn entry(card: Uuid, day: i64, rating: Difficulty, elapsed_days: u32, is_new: bool) -> ReviewLogEntry {
+        let memory = SchedulerMemory::Fsrs { stability: 1.0, difficulty: 5.0 };
+        ReviewLogEntry {
+            name: card,
+            content: card,
+            reviewed_at: DateTime::UNIX_EPOCH + chrono::Duration::days(day),
+            rating,
+            elapsed_days,
+            previous: if is_new { None } else { Some(memory) },
+            current: memory,
+            step: None,
+            time_spent: 0,
+            undone: false,
+        }
+    }
+
+    #[test]
+    fn test_build_training_set() {
+        let card1 = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"card_1");
+        let card2 = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"card_2");
+        let entries = vec![
+            entry(card1, 3, Difficulty::Good, 3, false),
+            entry(card1, 0, Difficulty::Again, 0, true),
+            entry(card2, 1, Difficulty::Good, 1, false),
+            entry(card1, 10, Difficulty::Easy, 7, false),
+        ];
+        let mut items = super::build_training_set(&entries);
+        items.sort_by_key(|item| item.reviews.len());
+
+        assert_eq!(items.len(), 2);
+        let ratings: Vec<_> = items[1].reviews.iter().map(|review| review.rating).collect();
+        let delta_ts: Vec<_> = items[1].reviews.iter().map(|review| review.delta_t).collect();
+        assert_eq!(ratings, vec![1, 3, 4]);
+        assert_eq!(delta_ts, vec![0, 3, 7]);
+        assert_eq!(items[0].reviews.len(), 2);
+    }
+}
@@ -0,0 +1,231 @@
+use chrono::{DateTime, Duration, Local, Utc};
+use crate::core::learning::{elapsed_days, study_day};
+use crate::core::RunningCore;
+use crate::repository::lock::CardItemIdentify;
+
+/// How long before their due time learning cards are shown when nothing else is left to study,
+/// as Anki's learn ahead limit.
+pub const LEARN_AHEAD_MINUTES: i64 = 20;
+
+impl RunningCore {
+    /// Whether a sibling of the card was studied today, which buries this one until tomorrow.
+    fn is_buried(&self, id: &CardItemIdentify) -> bool {
+        let Some(siblings) = self.siblings.get(id) else {
+            return false;
+        };
+        let now = Local::now();
+        let today = study_day(&now, self.deck.day_rollover_hour);
+        siblings.iter()
+            .filter_map(|sibling| self.lock_file.get(sibling).and_then(|item| item.state.as_ref()))
+            .any(|state| study_day(&state.last_reviewed.with_timezone(&Local), self.deck.day_rollover_hour) == today)
+    }
+    /// Due reviews, the ones most likely forgotten first.
+    pub(crate) fn due_reviews(&self) -> Vec<CardItemIdentify> {
+        self.due_reviews_by_retrievability().into_iter().map(|(id, _)| id).collect()
+    }
+    fn due_reviews_by_retrievability(&self) -> Vec<(CardItemIdentify, f32)> {
+        let now = Local::now();
+        let mut due: Vec<_> = self.lock_file.values()
+            .filter(|item| !item.ignored && item.state.is_some() && !item.is_learning() && item.filter_on_time(&now, self.deck.day_rollover_hour))
+            .filter(|item| self.cards.contains_key(&item.get_id()) && !self.is_buried(&item.get_id()))
+            .map(|item| {
+                let state = item.state.as_ref().unwrap();
+                let elapsed_days = elapsed_days(&state.last_reviewed, &now, self.deck.day_rollover_hour);
+                let retrievability = self.scheduler.retrievability(state, elapsed_days);
+                (retrievability, item.next_review(&Local, self.deck.day_rollover_hour), item.get_id())
+            })
+            .collect();
+        due.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        due.into_iter().map(|(retrievability, _, id)| (id, retrievability)).collect()
+    }
+    /// Cards in learning steps with their due time, the earliest first.
+    fn learning_cards(&self) -> Vec<(CardItemIdentify, DateTime<Utc>)> {
+        let mut learning: Vec<_> = self.lock_file.values()
+            .filter(|item| !item.ignored && item.is_learning() && self.cards.contains_key(&item.get_id()))
+            .filter_map(|item| Some((item.get_id(), item.next_review(&Local, self.deck.day_rollover_hour)?)))
+            .collect();
+        learning.sort_by_key(|(_, due)| *due);
+        learning
+    }
+    /// The earliest card in learning steps that is due, or with `ahead` due within the learn ahead limit.
+    /// Learning cards are not limited by the daily review limit.
+    pub(crate) fn next_learning(&self, ahead: bool) -> Option<(CardItemIdentify, DateTime<Utc>)> {
+        let mut limit = Utc::now();
+        if ahead {
+            limit += Duration::minutes(LEARN_AHEAD_MINUTES);
+        }
+        self.learning_cards().into_iter().find(|(_, due)| *due <= limit)
+    }
+    /// When the next card in learning steps is due, if it is still today.
+    pub(crate) fn next_learning_due(&self) -> Option<DateTime<Utc>> {
+        let today = study_day(&Local::now(), self.deck.day_rollover_hour);
+        self.learning_cards().into_iter()
+            .map(|(_, due)| due)
+            .find(|due| study_day(&due.with_timezone(&Local), self.deck.day_rollover_hour) == today)
+    }
+    /// Never-seen cards in the order they should be introduced.
+    pub(crate) fn new_cards(&self) -> Vec<CardItemIdentify> {
+        self.order.iter()
+            .filter(|id| self.lock_file.get(id).is_some_and(|item| !item.ignored && item.state.is_none()))
+            .filter(|id| !self.is_buried(id))
+            .copied()
+            .collect()
+    }
+    fn remaining_reviews(&self) -> usize {
+        self.deck.reviews_per_day.saturating_sub(self.counter.reviews)
+    }
+    fn remaining_new(&self) -> usize {
+        self.deck.new_per_day.saturating_sub(self.counter.new)
+    }
+    /// Number of reviews and new cards that can still be studied today.
+    pub(crate) fn queue_sizes(&self) -> (usize, usize) {
+        let now = Utc::now();
+        let learning = self.learning_cards().iter().filter(|(_, due)| *due <= now).count();
+        let due = learning + self.due_reviews().len().min(self.remaining_reviews());
+        let new = self.new_cards().len().min(self.remaining_new());
+        (due, new)
+    }
+    /// The most urgent due review with its retrievability, if today's review limit allows one.
+    pub(crate) fn next_review(&self) -> Option<(CardItemIdentify, f32)> {
+        if self.remaining_reviews() == 0 {
+            return None;
+        }
+        self.due_reviews_by_retrievability().into_iter().next()
+    }
+    /// The next never-seen card, if today's new card limit allows one.
+    pub(crate) fn next_new(&self) -> Option<CardItemIdentify> {
+        if self.remaining_new() == 0 {
+            return None;
+        }
+        self.new_cards().first().copied()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use std::time::Duration;
+    use chrono::{Local, Utc};
+    use crate::core::collection::Collection;
+    use crate::core::deck_loader::{create_or_update_lock_file, read_daily_counter, read_deck_meta_file, write_daily_counter};
+    use crate::core::learning::{study_day, Difficulty};
+    use crate::core::RunningCore;
+    use crate::repository::daily_counter::DailyCounter;
+    use crate::repository::lock::{CardItemIdentify, MemoryState, SchedulerMemory};
+
+    fn write_deck(dir: &Path, settings: &str, cards: usize) {
+        std::fs::write(dir.join("deck.yaml"), format!("name: test\ncard_files: [cards.yml]\n{settings}")).unwrap();
+        let cards: String = (0..cards).map(|card| format!("- name: card {card}\n  content: content {card}\n")).collect();
+        std::fs::write(dir.join("cards.yml"), cards).unwrap();
+    }
+
+    async fn open(dir: &Path) -> RunningCore {
+        let deck = read_deck_meta_file(dir).await.unwrap();
+        let loaded = create_or_update_lock_file(dir, &deck).await.unwrap();
+        RunningCore::new(dir.to_path_buf(), deck, loaded).await.unwrap()
+    }
+
+    fn id(core: &RunningCore, name: &str) -> CardItemIdentify {
+        core.cards.iter().find(|(_, card)| card.name == name).map(|(id, _)| *id).unwrap()
+    }
+
+    /// Make a card a review due today, last reviewed `days_ago` days ago.
+    fn set_review(core: &mut RunningCore, name: &str, days_ago: i64, stability: f32) {
+        let id = id(core, name);
+        core.lock_file.get_mut(&id).unwrap().state = Some(MemoryState {
+            last_reviewed: Utc::now() - chrono::Duration::days(days_ago),
+            interval: 1.0,
+            memory: SchedulerMemory::Fsrs { stability, difficulty: 5.0 },
+            step: None,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_daily_limits() {
+        let dir = tempfile::tempdir().unwrap();
+        write_deck(dir.path(), "new_per_day: 2\nreviews_per_day: 1\n", 5);
+        let mut core = open(dir.path()).await;
+        set_review(&mut core, "card 3", 10, 2.0);
+        set_review(&mut core, "card 4", 10, 2.0);
+        assert_eq!(core.queue_sizes(), (1, 2));
+
+        let (review, _) = core.next_review().unwrap();
+        core.next_state(review, Difficulty::Good, Duration::ZERO).await.unwrap();
+        assert_eq!(core.next_review(), None);
+        assert_eq!(core.due_reviews().len(), 1);
+        for _ in 0..2 {
+            let new = core.next_new().unwrap();
+            core.next_state(new, Difficulty::Good, Duration::ZERO).await.unwrap();
+        }
+        assert_eq!(core.next_new(), None);
+        assert_eq!(core.new_cards().len(), 1);
+        assert_eq!(core.queue_sizes(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_reviews_come_before_new_cards() {
+        let dir = tempfile::tempdir().unwrap();
+        write_deck(dir.path(), "", 3);
+        let mut core = open(dir.path()).await;
+        set_review(&mut core, "card 2", 10, 2.0);
+        let review = id(&core, "card 2");
+        let mut collection = Collection::new("test".to_string(), vec![core]);
+
+        let card = collection.next_card().unwrap();
+        assert_eq!(card.id, review);
+        collection.next_state(card, Difficulty::Good, Duration::ZERO).await.unwrap();
+        let card = collection.next_card().unwrap();
+        assert_eq!(collection.card(card).name, "card 0");
+    }
+
+    #[tokio::test]
+    async fn test_reviews_by_retrievability() {
+        let dir = tempfile::tempdir().unwrap();
+        write_deck(dir.path(), "", 3);
+        let mut core = open(dir.path()).await;
+        set_review(&mut core, "card 0", 3, 2.0);
+        set_review(&mut core, "card 1", 20, 2.0);
+        set_review(&mut core, "card 2", 3, 30.0);
+        let expected = ["card 1", "card 0", "card 2"].map(|name| id(&core, name));
+        assert_eq!(core.due_reviews(), expected);
+        assert_eq!(core.next_review().map(|(id, _)| id), Some(expected[0]));
+    }
+
+    #[tokio::test]
+    async fn test_new_card_order() {
+        let dir = tempfile::tempdir().unwrap();
+        write_deck(dir.path(), "", 30);
+        let core = open(dir.path()).await;
+        let sequential: Vec<_> = (0..30).map(|card| id(&core, &format!("card {card}"))).collect();
+        assert_eq!(core.new_cards(), sequential);
+
+        write_deck(dir.path(), "new_card_order: random\n", 30);
+        let core = open(dir.path()).await;
+        let random = core.new_cards();
+        assert_ne!(random, sequential);
+        let mut sorted = random.clone();
+        sorted.sort_by_key(|id| sequential.iter().position(|other| other == id));
+        assert_eq!(sorted, sequential);
+    }
+
+    #[tokio::test]
+    async fn test_counter_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        write_deck(dir.path(), "new_per_day: 1\n", 3);
+        let mut core = open(dir.path()).await;
+        let new = core.next_new().unwrap();
+        core.next_state(new, Difficulty::Good, Duration::ZERO).await.unwrap();
+        let today = study_day(&Local::now(), 4);
+        assert_eq!(read_daily_counter(dir.path()).await.unwrap(), Some(DailyCounter { date: today, new: 1, reviews: 0 }));
+
+        let core = open(dir.path()).await;
+        assert_eq!(core.next_new(), None);
+
+        // a counter of an earlier study day starts over
+        let yesterday = today.pred_opt().unwrap();
+        write_daily_counter(dir.path(), &DailyCounter { date: yesterday, new: 1, reviews: 0 }).await.unwrap();
+        let core = open(dir.path()).await;
+        assert_eq!(core.counter, DailyCounter::new(today));
+        assert_eq!(core.next_new(), Some(id(&core, "card 1")));
+    }
+}
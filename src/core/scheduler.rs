@@ -0,0 +1,234 @@
+use anyhow::Result;
+use fsrs::FSRS;
+use crate::core::learning::Difficulty;
+use crate::repository::deck::{default_retention, Deck, RepeatAlgorithm};
+use crate::repository::lock::{MemoryState, SchedulerMemory};
+
+/// A spaced repetition algorithm, keeping its own memory in the lock file.
+///
+/// A card may carry the memory of another algorithm when a deck switched algorithms;
+/// schedulers then continue from its converted memory.
+pub trait Scheduler {
+    /// The memory and interval in days after answering a card `elapsed_days` after its last review.
+    /// `state` is `None` for new cards.
+    fn next(&self, state: Option<&MemoryState>, difficulty: Difficulty, elapsed_days: u32) -> (SchedulerMemory, f32);
+    /// How likely a reviewed card is still remembered, used to order due reviews.
+    fn retrievability(&self, state: &MemoryState, elapsed_days: u32) -> f32;
+    /// The memory of this algorithm closest to `state`, which may have been kept by another one.
+    fn convert(&self, state: &MemoryState) -> SchedulerMemory;
+}
+
+/// The scheduler selected by the `algorithm` of the deck.
+pub fn for_deck(deck: &Deck) -> Result<Box<dyn Scheduler>> {
+    let scheduler: Box<dyn Scheduler> = match deck.algorithm {
+        RepeatAlgorithm::Fsrs => {
+            let weights = deck.fsrs_option.as_ref().and_then(|option| option.weights.as_deref());
+            let retention = deck.fsrs_option.as_ref().map_or(default_retention(), |option| option.retention);
+            Box::new(FsrsScheduler { fsrs: FSRS::new(Some(weights.unwrap_or(&[])))?, retention })
+        }
+        RepeatAlgorithm::Sm2 => Box::new(Sm2Scheduler),
+        RepeatAlgorithm::Leitner => Box::new(LeitnerScheduler),
+    };
+    Ok(scheduler)
+}
+
+/// Retention assumed for schedulers that do not model it, when converting to FSRS.
+const SM2_RETENTION: f32 = 0.9;
+
+/// Exponential forgetting reaching `SM2_RETENTION` when the interval is over,
+/// for schedulers without a memory model.
+fn interval_retrievability(state: &MemoryState, elapsed_days: u32) -> f32 {
+    SM2_RETENTION.powf(elapsed_days as f32 / state.interval.max(1.0))
+}
+
+/// FSRS difficulties range from 1 to 10.
+const FSRS_MEDIUM_DIFFICULTY: f32 = 5.0;
+
+pub struct FsrsScheduler {
+    fsrs: FSRS,
+    retention: f32,
+}
+
+impl FsrsScheduler {
+    /// The FSRS memory of a card, approximated from the ease and interval of other algorithms.
+    fn memory(&self, state: &MemoryState) -> fsrs::MemoryState {
+        let ease = match state.memory {
+            SchedulerMemory::Fsrs { stability, difficulty } => return fsrs::MemoryState { stability, difficulty },
+            SchedulerMemory::Sm2 { ease, .. } => ease,
+            SchedulerMemory::Leitner { .. } => SM2_INITIAL_EASE,
+        };
+        self.fsrs.memory_state_from_sm2(ease, state.interval, SM2_RETENTION)
+            .unwrap_or(fsrs::MemoryState { stability: state.interval, difficulty: FSRS_MEDIUM_DIFFICULTY })
+    }
+}
+
+impl Scheduler for FsrsScheduler {
+    fn next(&self, state: Option<&MemoryState>, difficulty: Difficulty, elapsed_days: u32) -> (SchedulerMemory, f32) {
+        let memory = state.map(|state| self.memory(state));
+        let states = self.fsrs.next_states(memory, self.retention, elapsed_days).unwrap();
+        let next = match difficulty {
+            Difficulty::Easy => states.easy,
+            Difficulty::Good => states.good,
+            Difficulty::Hard => states.hard,
+            Difficulty::Again => states.again,
+        };
+        let memory = SchedulerMemory::Fsrs { stability: next.memory.stability, difficulty: next.memory.difficulty };
+        (memory, next.interval)
+    }
+    fn retrievability(&self, state: &MemoryState, elapsed_days: u32) -> f32 {
+        self.fsrs.current_retrievability(self.memory(state), elapsed_days)
+    }
+    fn convert(&self, state: &MemoryState) -> SchedulerMemory {
+        let memory = self.memory(state);
+        SchedulerMemory::Fsrs { stability: memory.stability, difficulty: memory.difficulty }
+    }
+}
+
+const SM2_INITIAL_EASE: f32 = 2.5;
+const SM2_MINIMUM_EASE: f32 = 1.3;
+/// Ease given to the easiest cards converted from FSRS.
+const SM2_MAXIMUM_CONVERTED_EASE: f32 = 3.0;
+
+/// SuperMemo 2, the algorithm Anki used before FSRS.
+pub struct Sm2Scheduler;
+
+impl Scheduler for Sm2Scheduler {
+    fn next(&self, state: Option<&MemoryState>, difficulty: Difficulty, _elapsed_days: u32) -> (SchedulerMemory, f32) {
+        let (ease, repetitions, interval) = match state.map(|state| (self.convert(state), state.interval)) {
+            Some((SchedulerMemory::Sm2 { ease, repetitions }, interval)) => (ease, repetitions, interval),
+            _ => (SM2_INITIAL_EASE, 0, 0.0),
+        };
+        // the four answers stand for the SM-2 grades 1, 3, 4 and 5
+        let quality = match difficulty {
+            Difficulty::Again => 1.0,
+            Difficulty::Hard => 3.0,
+            Difficulty::Good => 4.0,
+            Difficulty::Easy => 5.0,
+        };
+        let ease = (ease + 0.1 - (5.0 - quality) * (0.08 + (5.0 - quality) * 0.02)).max(SM2_MINIMUM_EASE);
+        if difficulty == Difficulty::Again {
+            return (SchedulerMemory::Sm2 { ease, repetitions: 0 }, 1.0);
+        }
+        let interval = match repetitions {
+            0 => 1.0,
+            1 => 6.0,
+            _ => (interval * ease).round().max(1.0),
+        };
+        (SchedulerMemory::Sm2 { ease, repetitions: repetitions + 1 }, interval)
+    }
+    fn retrievability(&self, state: &MemoryState, elapsed_days: u32) -> f32 {
+        interval_retrievability(state, elapsed_days)
+    }
+    fn convert(&self, state: &MemoryState) -> SchedulerMemory {
+        let ease = match state.memory {
+            SchedulerMemory::Sm2 { .. } => return state.memory,
+            // easy FSRS cards get a high ease, hard ones the minimum
+            SchedulerMemory::Fsrs { difficulty, .. } => {
+                let easiness = (10.0 - difficulty.clamp(1.0, 10.0)) / 9.0;
+                SM2_MINIMUM_EASE + easiness * (SM2_MAXIMUM_CONVERTED_EASE - SM2_MINIMUM_EASE)
+            }
+            SchedulerMemory::Leitner { .. } => SM2_INITIAL_EASE,
+        };
+        // intervals past the fixed first two steps grow by the ease
+        let repetitions = match state.interval {
+            interval if interval >= 6.0 => 2,
+            interval if interval >= 1.0 => 1,
+            _ => 0,
+        };
+        SchedulerMemory::Sm2 { ease, repetitions }
+    }
+}
+
+/// Days until the next review of the cards in each Leitner box.
+const LEITNER_INTERVALS: [f32; 7] = [1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+
+/// Leitner boxes: remembered cards move up a box, forgotten ones go back to the first.
+pub struct LeitnerScheduler;
+
+impl Scheduler for LeitnerScheduler {
+    fn next(&self, state: Option<&MemoryState>, difficulty: Difficulty, _elapsed_days: u32) -> (SchedulerMemory, f32) {
+        let current = state.map(|state| match self.convert(state) {
+            SchedulerMemory::Leitner { leitner_box } => leitner_box,
+            _ => unreachable!("Leitner memory is converted to a box"),
+        });
+        let next = match (current, difficulty) {
+            (_, Difficulty::Again) => 0,
+            (None, Difficulty::Easy) => 1,
+            (None, _) => 0,
+            (Some(current), Difficulty::Hard) => current,
+            (Some(current), Difficulty::Good) => current + 1,
+            (Some(current), Difficulty::Easy) => current + 2,
+        };
+        let leitner_box = next.min(LEITNER_INTERVALS.len() as u32 - 1);
+        (SchedulerMemory::Leitner { leitner_box }, LEITNER_INTERVALS[leitner_box as usize])
+    }
+    fn retrievability(&self, state: &MemoryState, elapsed_days: u32) -> f32 {
+        interval_retrievability(state, elapsed_days)
+    }
+    fn convert(&self, state: &MemoryState) -> SchedulerMemory {
+        match state.memory {
+            SchedulerMemory::Leitner { .. } => state.memory,
+            // a card reviewed with another algorithm goes to the box of its interval
+            _ => {
+                let leitner_box = LEITNER_INTERVALS.iter().rposition(|interval| *interval <= state.interval).unwrap_or(0);
+                SchedulerMemory::Leitner { leitner_box: leitner_box as u32 }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::DateTime;
+    use crate::core::learning::Difficulty;
+    use crate::repository::lock::{MemoryState, SchedulerMemory};
+    use super::{LeitnerScheduler, Scheduler, Sm2Scheduler};
+
+    fn answer(scheduler: &dyn Scheduler, answers: &[Difficulty]) -> Vec<(SchedulerMemory, f32)> {
+        let mut state: Option<MemoryState> = None;
+        let mut results = Vec::new();
+        for difficulty in answers {
+            let (memory, interval) = scheduler.next(state.as_ref(), *difficulty, 0);
+            state = Some(MemoryState { last_reviewed: DateTime::UNIX_EPOCH, interval, memory, step: None });
+            results.push((memory, interval));
+        }
+        results
+    }
+
+    #[test]
+    fn test_sm2_intervals() {
+        use Difficulty::*;
+        let results = answer(&Sm2Scheduler, &[Good, Good, Good, Again, Good]);
+        let intervals: Vec<_> = results.iter().map(|(_, interval)| *interval).collect();
+        assert_eq!(intervals, vec![1.0, 6.0, 15.0, 1.0, 1.0]);
+        let SchedulerMemory::Sm2 { ease, repetitions } = results[4].0 else {
+            unreachable!();
+        };
+        assert!((ease - 1.96).abs() < 1e-5);
+        assert_eq!(repetitions, 1);
+    }
+
+    #[test]
+    fn test_leitner_boxes() {
+        use Difficulty::*;
+        let results = answer(&LeitnerScheduler, &[Good, Good, Easy, Hard, Again, Easy, Easy, Easy, Easy]);
+        let boxes: Vec<_> = results.iter().map(|(memory, _)| match memory {
+            SchedulerMemory::Leitner { leitner_box } => *leitner_box,
+            _ => unreachable!(),
+        }).collect();
+        assert_eq!(boxes, vec![0, 1, 3, 3, 0, 2, 4, 6, 6]);
+        assert_eq!(results[2].1, 8.0);
+    }
+
+    #[test]
+    fn test_convert_between_algorithms() {
+        let state = |memory| MemoryState { last_reviewed: DateTime::UNIX_EPOCH, interval: 10.0, memory, step: None };
+        let easy = state(SchedulerMemory::Fsrs { stability: 10.0, difficulty: 1.0 });
+        let hard = state(SchedulerMemory::Fsrs { stability: 10.0, difficulty: 10.0 });
+        assert_eq!(Sm2Scheduler.convert(&easy), SchedulerMemory::Sm2 { ease: 3.0, repetitions: 2 });
+        assert_eq!(Sm2Scheduler.convert(&hard), SchedulerMemory::Sm2 { ease: 1.3, repetitions: 2 });
+        assert_eq!(LeitnerScheduler.convert(&easy), SchedulerMemory::Leitner { leitner_box: 3 });
+        let sm2 = state(SchedulerMemory::Sm2 { ease: 2.5, repetitions: 3 });
+        assert_eq!(Sm2Scheduler.convert(&sm2), sm2.memory);
+    }
+}
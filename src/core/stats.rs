@@ -0,0 +1,231 @@
+use std::fmt::Display;
+use std::path::Path;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use crate::core::deck_loader::{read_lock_file, read_review_log};
+use crate::core::learning::{study_day, Difficulty};
+use crate::repository::deck::Deck;
+use crate::repository::lock::{LockItem, SchedulerMemory};
+use crate::repository::review_log::ReviewLogEntry;
+
+/// Number of days covered by the due forecast.
+const FORECAST_DAYS: usize = 30;
+const HISTOGRAM_WIDTH: usize = 40;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeckStats {
+    pub deck: String,
+    pub total: usize,
+    pub new: usize,
+    /// Cards going through learning or relearning steps.
+    pub learning: usize,
+    pub review: usize,
+    pub ignored: usize,
+    /// Due today, including overdue cards.
+    pub due_today: usize,
+    pub due_7_days: usize,
+    pub due_30_days: usize,
+    pub average_stability: Option<f32>,
+    pub average_difficulty: Option<f32>,
+    /// Share of reviews of cards with a long-term interval that were not answered with `again`,
+    /// leaving out answers at learning and relearning steps.
+    pub true_retention: Option<f32>,
+    pub reviews: usize,
+    /// Cards falling due on each of the next days, today (with overdue cards) first.
+    pub forecast: Vec<usize>,
+}
+
+fn average(values: &[f32]) -> Option<f32> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f32>() / values.len() as f32)
+    }
+}
+
+impl DeckStats {
+    pub fn new(deck: &Deck, lock_items: &[LockItem], review_log: &[ReviewLogEntry], now: DateTime<Local>) -> Self {
+        let today = study_day(&now, deck.day_rollover_hour);
+        let mut stats = DeckStats {
+            deck: deck.name.clone(),
+            total: lock_items.len(),
+            new: 0,
+            learning: 0,
+            review: 0,
+            ignored: 0,
+            due_today: 0,
+            due_7_days: 0,
+            due_30_days: 0,
+            average_stability: None,
+            average_difficulty: None,
+            true_retention: None,
+            reviews: 0,
+            forecast: vec![0; FORECAST_DAYS],
+        };
+        let mut stabilities = Vec::new();
+        let mut difficulties = Vec::new();
+        for item in lock_items {
+            if item.ignored {
+                stats.ignored += 1;
+                continue;
+            }
+            let (Some(state), Some(next_review)) = (item.state.as_ref(), item.next_review(&Local, deck.day_rollover_hour)) else {
+                stats.new += 1;
+                continue;
+            };
+            if item.is_learning() {
+                stats.learning += 1;
+            } else {
+                stats.review += 1;
+            }
+            if let SchedulerMemory::Fsrs { stability, difficulty } = state.memory {
+                stabilities.push(stability);
+                difficulties.push(difficulty);
+            }
+            let due_day = study_day(&next_review.with_timezone(&Local), deck.day_rollover_hour);
+            let days_ahead = (due_day - today).num_days().max(0) as usize;
+            if days_ahead == 0 {
+                stats.due_today += 1;
+            }
+            if days_ahead <= 7 {
+                stats.due_7_days += 1;
+            }
+            if days_ahead <= 30 {
+                stats.due_30_days += 1;
+            }
+            if days_ahead < FORECAST_DAYS {
+                stats.forecast[days_ahead] += 1;
+            }
+        }
+        stats.average_stability = average(&stabilities);
+        stats.average_difficulty = average(&difficulties);
+
+        let recalls: Vec<_> = review_log.iter().filter(|entry| entry.is_review()).collect();
+        stats.reviews = recalls.len();
+        if !recalls.is_empty() {
+            let passed = recalls.iter().filter(|entry| entry.rating != Difficulty::Again).count();
+            stats.true_retention = Some(passed as f32 / recalls.len() as f32);
+        }
+        stats
+    }
+}
+
+/// Statistics of the deck in `dir`, whose lock file must be up to date.
+pub async fn collect(dir: &Path, deck: &Deck) -> Result<DeckStats> {
+    let lock_items = read_lock_file(dir).await?;
+    let review_log = read_review_log(dir).await?;
+    Ok(DeckStats::new(deck, &lock_items, &review_log, Local::now()))
+}
+
+fn format_optional(value: Option<f32>, precision: usize) -> String {
+    match value {
+        None => "-".to_string(),
+        Some(value) => format!("{:.*}", precision, value),
+    }
+}
+
+impl Display for DeckStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.deck)?;
+        writeln!(f, "  total: {}  new: {}  learning: {}  review: {}  ignored: {}",
+                 self.total, self.new, self.learning, self.review, self.ignored)?;
+        writeln!(f, "  due today: {}  next 7 days: {}  next 30 days: {}",
+                 self.due_today, self.due_7_days, self.due_30_days)?;
+        writeln!(f, "  average stability: {} days  average difficulty: {}",
+                 format_optional(self.average_stability, 2), format_optional(self.average_difficulty, 2))?;
+        let retention = match self.true_retention {
+            None => "-".to_string(),
+            Some(retention) => format!("{:.1}%", retention * 100.0),
+        };
+        writeln!(f, "  true retention: {} ({} reviews)", retention, self.reviews)?;
+        writeln!(f, "  due forecast:")?;
+        let max = self.forecast.iter().copied().max().unwrap_or(0).max(1);
+        for (day, count) in self.forecast.iter().enumerate() {
+            let bar = "#".repeat(count * HISTOGRAM_WIDTH / max);
+            writeln!(f, "  {:>4} | {:<width$} {}", format!("+{}d", day), bar, count, width = HISTOGRAM_WIDTH)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Local, TimeZone, Utc};
+    use uuid::Uuid;
+    use crate::core::learning::Difficulty;
+    use crate::repository::deck::Deck;
+    use crate::repository::lock::{LearningPhase, LockItem, MemoryState, SchedulerMemory};
+    use crate::repository::review_log::ReviewLogEntry;
+    use super::DeckStats;
+
+    #[test]
+    fn test_deck_stats() {
+        let deck = Deck::new("deck".to_string(), Vec::new());
+        let now = Local.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap();
+        let memory = |stability, difficulty| SchedulerMemory::Fsrs { stability, difficulty };
+        let item = |card: &str, state: Option<MemoryState>, ignored| {
+            let id = Uuid::new_v5(&Uuid::NAMESPACE_OID, card.as_bytes());
+            LockItem { name: id, content: id, state, ignored, content_hash: None }
+        };
+        // a card last reviewed `days_ago` days ago with an `interval` of days
+        let reviewed = |days_ago, interval, stability, difficulty| Some(MemoryState {
+            last_reviewed: (now - Duration::days(days_ago)).with_timezone(&Utc),
+            interval,
+            memory: memory(stability, difficulty),
+            step: None,
+        });
+        let learning = MemoryState {
+            last_reviewed: (now - Duration::minutes(5)).with_timezone(&Utc),
+            interval: 10.0 / 1440.0,
+            memory: memory(1.0, 5.0),
+            step: Some(LearningPhase::Learning(0)),
+        };
+        let lock_items = vec![
+            item("new", None, false),
+            item("ignored", reviewed(1, 3.0, 9.0, 9.0), true),
+            item("learning", Some(learning), false),
+            item("overdue", reviewed(10, 3.0, 2.0, 4.0), false),
+            item("due today", reviewed(3, 3.0, 3.0, 6.0), false),
+            item("in 5 days", reviewed(1, 6.0, 4.0, 5.0), false),
+            item("in 20 days", reviewed(0, 20.0, 5.0, 5.0), false),
+            item("in 100 days", reviewed(0, 100.0, 15.0, 5.0), false),
+        ];
+
+        let card = Uuid::new_v5(&Uuid::NAMESPACE_OID, b"card");
+        let answer = |rating, previous: bool, step| ReviewLogEntry {
+            name: card,
+            content: card,
+            reviewed_at: now.with_timezone(&Utc),
+            rating,
+            elapsed_days: 0,
+            previous: previous.then_some(memory(1.0, 5.0)),
+            current: memory(1.0, 5.0),
+            step,
+            time_spent: 0,
+            undone: false,
+        };
+        // only the answers of cards with a long-term interval count toward the retention
+        let review_log = vec![
+            answer(Difficulty::Again, false, None),
+            answer(Difficulty::Again, true, Some(LearningPhase::Learning(0))),
+            answer(Difficulty::Good, true, None),
+            answer(Difficulty::Again, true, None),
+            answer(Difficulty::Again, true, Some(LearningPhase::Relearning(0))),
+            answer(Difficulty::Easy, true, None),
+        ];
+
+        let stats = DeckStats::new(&deck, &lock_items, &review_log, now);
+        assert_eq!((stats.total, stats.new, stats.learning, stats.review, stats.ignored), (8, 1, 1, 5, 1));
+        assert_eq!((stats.due_today, stats.due_7_days, stats.due_30_days), (3, 4, 5));
+        let mut forecast = vec![0; 30];
+        forecast[0] = 3;
+        forecast[5] = 1;
+        forecast[20] = 1;
+        assert_eq!(stats.forecast, forecast);
+        assert_eq!(stats.average_stability, Some(5.0));
+        assert_eq!(stats.average_difficulty, Some(5.0));
+        assert_eq!(stats.reviews, 3);
+        assert_eq!(stats.true_retention, Some(2.0 / 3.0));
+    }
+}
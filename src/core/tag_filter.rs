@@ -0,0 +1,210 @@
+use std::path::{Component, Path};
+use anyhow::{anyhow, bail, Result};
+use crate::repository::deck::CardItem;
+
+/// A boolean expression over card tags, e.g. `greeting and not (sentence or noun)`.
+///
+/// `&`, `|` and `!` can be used in place of `and`, `or` and `not`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(input: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => { chars.next(); }
+            '(' => { chars.next(); tokens.push(Token::Open); }
+            ')' => { chars.next(); tokens.push(Token::Close); }
+            '&' => { chars.next(); tokens.push(Token::And); }
+            '|' => { chars.next(); tokens.push(Token::Or); }
+            '!' => { chars.next(); tokens.push(Token::Not); }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()&|!".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Tag(word),
+                });
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    fn parse_or(&mut self) -> Result<TagExpr> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+    fn parse_and(&mut self) -> Result<TagExpr> {
+        let mut expr = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = TagExpr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+    fn parse_not(&mut self) -> Result<TagExpr> {
+        match self.next() {
+            Some(Token::Not) => Ok(TagExpr::Not(Box::new(self.parse_not()?))),
+            Some(Token::Tag(tag)) => Ok(TagExpr::Tag(tag)),
+            Some(Token::Open) => {
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => bail!("Missing closing parenthesis"),
+                }
+            }
+            Some(token) => Err(anyhow!("Unexpected {:?} in tag expression", token)),
+            None => bail!("Unexpected end of tag expression"),
+        }
+    }
+}
+
+impl TagExpr {
+    pub fn parse(input: &str) -> Result<TagExpr> {
+        let mut parser = Parser { tokens: tokenize(input), position: 0 };
+        let expr = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {:?} in tag expression", token);
+        }
+        Ok(expr)
+    }
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            TagExpr::Tag(tag) => tags.contains(tag),
+            TagExpr::Not(expr) => !expr.matches(tags),
+            TagExpr::And(left, right) => left.matches(tags) && right.matches(tags),
+            TagExpr::Or(left, right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+/// Restricts a study session to a part of the deck.
+#[derive(Debug, Clone, Default)]
+pub struct StudyFilter {
+    /// A card must match at least one of these expressions, if any is given.
+    pub tags: Vec<TagExpr>,
+    pub exclude_tags: Vec<String>,
+    /// A card must come from one of these card files, if any is given.
+    pub files: Vec<String>,
+}
+
+impl StudyFilter {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.exclude_tags.is_empty() && self.files.is_empty()
+    }
+    /// Whether the card from `file` of the deck at `dir` is studied.
+    pub fn matches(&self, card: &CardItem, dir: &Path, file: &str) -> bool {
+        let tags = card.tags.as_deref().unwrap_or_default();
+        let file_matches = self.files.is_empty() || self.files.iter().any(|selected| same_file(dir, selected, file));
+        let tag_matches = self.tags.is_empty() || self.tags.iter().any(|expr| expr.matches(tags));
+        let excluded = tags.iter().any(|tag| self.exclude_tags.contains(tag));
+        file_matches && tag_matches && !excluded
+    }
+}
+
+/// `file` relative to the deck at `dir`, with `/` separators and `.` and `..` resolved,
+/// or `None` when it lies outside the deck.
+fn deck_relative(dir: &Path, file: &str) -> Option<String> {
+    let path = Path::new(file);
+    let path = if path.is_absolute() { path.strip_prefix(dir).ok()? } else { path };
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Whether `selected`, like a `--file` argument, names the card file `file` of the deck at `dir`.
+pub(crate) fn same_file(dir: &Path, selected: &str, file: &str) -> bool {
+    deck_relative(dir, selected).is_some_and(|selected| deck_relative(dir, file) == Some(selected))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use super::{same_file, TagExpr};
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tag_expression() {
+        let expr = TagExpr::parse("greeting and not (sentence | noun)").unwrap();
+        assert!(expr.matches(&tags(&["greeting"])));
+        assert!(!expr.matches(&tags(&["greeting", "noun"])));
+        assert!(!expr.matches(&tags(&["sentence"])));
+
+        let expr = TagExpr::parse("a | b & !c").unwrap();
+        assert!(expr.matches(&tags(&["a", "c"])));
+        assert!(expr.matches(&tags(&["b"])));
+        assert!(!expr.matches(&tags(&["b", "c"])));
+
+        assert!(TagExpr::parse("(a or b").is_err());
+        assert!(TagExpr::parse("a b").is_err());
+        assert!(TagExpr::parse("").is_err());
+    }
+
+    #[test]
+    fn test_same_file() {
+        let dir = Path::new("/decks/japanese");
+        assert!(same_file(dir, "n5/vocab.yml", "n5/vocab.yml"));
+        assert!(same_file(dir, "./n5/vocab.yml", "n5/vocab.yml"));
+        assert!(same_file(dir, "n5/vocab.yml", "./n5//vocab.yml"));
+        assert!(same_file(dir, "n4/../n5/vocab.yml", "n5/vocab.yml"));
+        assert!(same_file(dir, "/decks/japanese/n5/vocab.yml", "n5/vocab.yml"));
+        assert!(!same_file(dir, "vocab.yml", "n5/vocab.yml"));
+        assert!(!same_file(dir, "../japanese/n5/vocab.yml", "n5/vocab.yml"));
+        assert!(!same_file(dir, "/decks/other/n5/vocab.yml", "n5/vocab.yml"));
+    }
+}
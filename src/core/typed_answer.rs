@@ -0,0 +1,164 @@
+use crate::core::learning::Difficulty;
+use crate::repository::deck::TypedAnswerOption;
+
+/// Similarity from which a typed answer with small mistakes is still rated hard instead of again.
+const HARD_SIMILARITY: f32 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    /// Typed as expected.
+    Same,
+    /// Expected but not typed.
+    Missing,
+    /// Typed but not expected.
+    Extra,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffSegment {
+    pub kind: DiffKind,
+    pub text: String,
+}
+
+/// The comparison of a typed answer with the expected one.
+#[derive(Debug, Clone)]
+pub struct AnswerCheck {
+    pub segments: Vec<DiffSegment>,
+    /// From 0 for nothing in common to 1 for equal answers.
+    pub similarity: f32,
+    pub suggested: Difficulty,
+}
+
+const HALF_WIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン";
+
+const VOICEABLE_KATAKANA: &str = "カキクケコサシスセソタチツテトハヒフヘホ";
+
+/// Fold full-width latin letters, digits and symbols to ASCII and half-width katakana to full-width,
+/// combining the separate voiced sound marks.
+fn fold_width(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{3000}' => folded.push(' '),
+            '\u{FF01}'..='\u{FF5E}' => folded.extend(char::from_u32(c as u32 - 0xFEE0)),
+            '\u{FF61}'..='\u{FF9D}' => folded.extend(HALF_WIDTH_KATAKANA.chars().nth((c as u32 - 0xFF61) as usize)),
+            '\u{FF9E}' | '\u{FF9F}' => {
+                let combined = folded.chars().last().and_then(|last| match (c, last) {
+                    ('\u{FF9E}', 'ウ') => Some('ヴ'),
+                    ('\u{FF9E}', _) if VOICEABLE_KATAKANA.contains(last) => char::from_u32(last as u32 + 1),
+                    ('\u{FF9F}', 'ハ' | 'ヒ' | 'フ' | 'ヘ' | 'ホ') => char::from_u32(last as u32 + 2),
+                    _ => None,
+                });
+                match combined {
+                    Some(combined) => {
+                        folded.pop();
+                        folded.push(combined);
+                    }
+                    None => folded.push(if c == '\u{FF9E}' { '゛' } else { '゜' }),
+                }
+            }
+            _ => folded.push(c),
+        }
+    }
+    folded
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || matches!(c, '\u{2000}'..='\u{206F}' | '\u{3001}'..='\u{303F}' | '・' | '\u{FF01}'..='\u{FF0F}')
+}
+
+/// Apply the normalizations enabled in `option` before comparing answers.
+pub fn normalize(text: &str, option: &TypedAnswerOption) -> String {
+    let mut text = if option.normalize_width { fold_width(text) } else { text.to_string() };
+    if option.ignore_case {
+        text = text.to_lowercase();
+    }
+    if option.ignore_punctuation {
+        text.retain(|c| !is_punctuation(c));
+    }
+    if option.collapse_whitespace {
+        text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    text
+}
+
+/// Character diff of `typed` against `expected` through their longest common subsequence.
+fn diff(typed: &[char], expected: &[char]) -> (Vec<DiffSegment>, usize) {
+    let mut lengths = vec![vec![0usize; expected.len() + 1]; typed.len() + 1];
+    for i in (0..typed.len()).rev() {
+        for j in (0..expected.len()).rev() {
+            lengths[i][j] = if typed[i] == expected[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut segments: Vec<DiffSegment> = Vec::new();
+    let mut push = |kind: DiffKind, c: char| match segments.last_mut() {
+        Some(last) if last.kind == kind => last.text.push(c),
+        _ => segments.push(DiffSegment { kind, text: c.to_string() }),
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < typed.len() || j < expected.len() {
+        if i < typed.len() && j < expected.len() && typed[i] == expected[j] {
+            push(DiffKind::Same, typed[i]);
+            i += 1;
+            j += 1;
+        } else if j < expected.len() && (i == typed.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            push(DiffKind::Missing, expected[j]);
+            j += 1;
+        } else {
+            push(DiffKind::Extra, typed[i]);
+            i += 1;
+        }
+    }
+    (segments, lengths[0][0])
+}
+
+/// Compare a typed answer with the expected one and suggest a rating for it.
+pub fn check_answer(typed: &str, expected: &str, option: &TypedAnswerOption) -> AnswerCheck {
+    let typed: Vec<char> = normalize(typed, option).chars().collect();
+    let expected: Vec<char> = normalize(expected, option).chars().collect();
+    let (segments, common) = diff(&typed, &expected);
+    let total = typed.len() + expected.len();
+    let similarity = if total == 0 { 1.0 } else { (2 * common) as f32 / total as f32 };
+    let suggested = if typed == expected {
+        Difficulty::Good
+    } else if similarity >= HARD_SIMILARITY {
+        Difficulty::Hard
+    } else {
+        Difficulty::Again
+    };
+    AnswerCheck { segments, similarity, suggested }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::learning::Difficulty;
+    use crate::repository::deck::TypedAnswerOption;
+    use super::{check_answer, normalize, DiffKind};
+
+    #[test]
+    fn test_normalize() {
+        let option = TypedAnswerOption { ignore_punctuation: true, ..TypedAnswerOption::default() };
+        assert_eq!(normalize("  Ｈｅｌｌｏ,\tWorld! ", &option), "hello world");
+        assert_eq!(normalize("ｶﾞｯｺｳ　ﾊﾟﾝ", &option), "ガッコウ パン");
+        assert_eq!(normalize("東京、日本。", &option), "東京日本");
+    }
+
+    #[test]
+    fn test_check_answer() {
+        let option = TypedAnswerOption::default();
+        let exact = check_answer("Tokyo ", "tokyo", &option);
+        assert_eq!(exact.suggested, Difficulty::Good);
+        assert_eq!(exact.similarity, 1.0);
+
+        let typo = check_answer("tokio", "tokyo", &option);
+        assert_eq!(typo.suggested, Difficulty::Hard);
+        let kinds: Vec<_> = typo.segments.iter().map(|segment| (segment.kind, segment.text.as_str())).collect();
+        assert_eq!(kinds, vec![(DiffKind::Same, "tok"), (DiffKind::Missing, "y"), (DiffKind::Extra, "i"), (DiffKind::Same, "o")]);
+
+        assert_eq!(check_answer("osaka", "tokyo", &option).suggested, Difficulty::Again);
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use anyhow::Result;
+use chrono::{DateTime, Local, Utc};
+use rusqlite::{params, Connection};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use tempfile::NamedTempFile;
+use zip::write::SimpleFileOptions;
+use crate::core::deck_loader::{read_lock_file, read_review_log, LoadedCards};
+use crate::core::learning::Difficulty;
+use crate::interop::anki_import::FIELD_SEPARATOR;
+use crate::repository::deck::{default_retention, CardItem, CardVariant, Deck};
+use crate::repository::lock::{LearningPhase, LockItem, MemoryState, SchedulerMemory};
+use crate::repository::review_log::ReviewLogEntry;
+
+const SCHEMA: &str = r#"
+CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null, scm integer not null, ver integer not null, dty integer not null, usn integer not null, ls integer not null, conf text not null, models text not null, decks text not null, dconf text not null, tags text not null);
+CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null, mod integer not null, usn integer not null, tags text not null, flds text not null, sfld integer not null, csum integer not null, flags integer not null, data text not null);
+CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null, ord integer not null, mod integer not null, usn integer not null, type integer not null, queue integer not null, due integer not null, ivl integer not null, factor integer not null, reps integer not null, lapses integer not null, left integer not null, odue integer not null, odid integer not null, flags integer not null, data text not null);
+CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null, ease integer not null, ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null, type integer not null);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+"#;
+
+const CARD_CSS: &str = ".card { font-family: arial; font-size: 20px; text-align: center; }";
+const CLOZE_CSS: &str = ".card { font-family: arial; font-size: 20px; text-align: center; }\n.cloze { font-weight: bold; color: blue; }";
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+pub struct AnkiExportOptions {
+    /// Carry the FSRS state and review history over, instead of exporting new cards.
+    pub with_scheduling: bool,
+}
+
+/// A card of the deck with what is known about its progress.
+/// The cards of a cloze entry follow each other and are exported as a single note.
+pub(super) struct ExportCard {
+    pub card: CardItem,
+    pub item: Option<LockItem>,
+    pub reviews: Vec<ReviewLogEntry>,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+/// Anki detects duplicates by the first 8 hex digits of the SHA-1 of the sort field.
+fn field_checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    i64::from(u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]))
+}
+
+/// The back of a note: `text`, after the glance of the card if it has one.
+fn card_back(card: &CardItem, text: &str) -> String {
+    match card.glance {
+        None => escape_html(text),
+        Some(ref glance) => format!("<i>{}</i><br><br>{}", escape_html(glance), escape_html(text)),
+    }
+}
+
+/// The note type of basic entries and the Cloze note type, of which Anki makes a card per deletion number.
+fn collection_json(deck_name: &str, deck_id: i64, model_id: i64, cloze_model_id: i64, now: i64) -> (String, String, String, String) {
+    let field = |name: &str, ord: u32| json!({
+        "name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": []
+    });
+    let model = |id: i64, name: &str, model_type: u32, template: serde_json::Value, fields: [&str; 2], css: &str| json!({
+        "id": id, "name": name, "type": model_type, "mod": now, "usn": -1, "sortf": 0, "did": deck_id,
+        "tmpls": [template],
+        "flds": [field(fields[0], 0), field(fields[1], 1)],
+        "css": css,
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false,
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": []
+    });
+    let basic = model(model_id, "mem-yaml Basic", 0, json!({
+        "name": "Card 1", "ord": 0, "qfmt": "{{Front}}", "afmt": "{{FrontSide}}<hr id=answer>{{Back}}",
+        "did": null, "bqfmt": "", "bafmt": ""
+    }), ["Front", "Back"], CARD_CSS);
+    let cloze = model(cloze_model_id, "mem-yaml Cloze", 1, json!({
+        "name": "Cloze", "ord": 0, "qfmt": "{{cloze:Text}}", "afmt": "{{cloze:Text}}<br>{{Back Extra}}",
+        "did": null, "bqfmt": "", "bafmt": ""
+    }), ["Text", "Back Extra"], CLOZE_CSS);
+    let models = json!({ model_id.to_string(): basic, cloze_model_id.to_string(): cloze });
+    let deck = |id: i64, name: &str| json!({
+        "id": id, "name": name, "conf": 1, "desc": "", "dyn": 0, "collapsed": false, "browserCollapsed": false,
+        "extendNew": 0, "extendRev": 0, "mod": now, "usn": -1,
+        "newToday": [0, 0], "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0]
+    });
+    let decks = json!({ "1": deck(1, "Default"), deck_id.to_string(): deck(deck_id, deck_name) });
+    let dconf = json!({ "1": {
+        "id": 1, "name": "Default", "mod": 0, "usn": 0, "maxTaken": 60, "autoplay": true, "timer": 0,
+        "replayq": true, "dyn": false,
+        "new": { "bury": false, "delays": [1.0, 10.0], "initialFactor": 2500, "ints": [1, 4, 0], "order": 1, "perDay": 20 },
+        "rev": { "bury": false, "ease4": 1.3, "ivlFct": 1.0, "maxIvl": 36500, "perDay": 200, "hardFactor": 1.2 },
+        "lapse": { "delays": [10.0], "leechAction": 1, "leechFails": 8, "minInt": 1, "mult": 0.0 }
+    }});
+    let conf = json!({
+        "activeDecks": [1], "curDeck": 1, "newSpread": 0, "collapseTime": 1200, "timeLim": 0,
+        "estTimes": true, "dueCounts": true, "curModel": model_id.to_string(), "nextPos": 1,
+        "sortType": "noteFld", "sortBackwards": false, "addToCur": true
+    });
+    (conf.to_string(), models.to_string(), decks.to_string(), dconf.to_string())
+}
+
+/// Anki card type, queue, due and interval of an exported card.
+fn card_schedule(export_card: &ExportCard, position: i64, collection_created: i64, rollover_hour: u32) -> (i64, i64, i64, i64) {
+    let Some(next_review) = export_card.item.as_ref().and_then(|item| item.next_review(&Local, rollover_hour)) else {
+        return (0, 0, position, 0);
+    };
+    let interval = export_card.item.as_ref().and_then(|item| item.state.as_ref())
+        .map_or(1, |state| state.interval.round().max(1.0) as i64);
+    let due = (next_review.timestamp() - collection_created) / SECONDS_PER_DAY;
+    (2, 2, due, interval)
+}
+
+pub(super) fn write_package(output: &Path, deck_name: &str, retention: f32, rollover_hour: u32, cards: &[ExportCard], now: DateTime<Utc>) -> Result<()> {
+    let collection = NamedTempFile::new()?;
+    let connection = Connection::open(collection.path())?;
+    connection.execute_batch(SCHEMA)?;
+
+    let now_ms = now.timestamp_millis();
+    let now_s = now.timestamp();
+    let deck_id = now_ms;
+    let model_id = now_ms + 1;
+    let cloze_model_id = now_ms + 2;
+    // due days of review cards count from the collection creation, keep them positive
+    let earliest_review = cards.iter()
+        .filter_map(|card| card.item.as_ref()?.state.as_ref().map(|state| state.last_reviewed.timestamp()))
+        .min()
+        .unwrap_or(now_s)
+        .min(now_s);
+    let collection_created = earliest_review - earliest_review.rem_euclid(SECONDS_PER_DAY);
+    let (conf, models, decks, dconf) = collection_json(deck_name, deck_id, model_id, cloze_model_id, now_s);
+    connection.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?3, 11, 0, 0, 0, ?4, ?5, ?6, ?7, '{}')",
+        params![collection_created, now_ms, now_ms, conf, models, decks, dconf],
+    )?;
+
+    let mut revlog_ids = std::collections::HashSet::new();
+    let mut note = None;
+    for (index, export_card) in cards.iter().enumerate() {
+        let card = &export_card.card;
+        let entry = card.entry();
+        let card_id = now_ms + 10 * index as i64 + 11;
+        let note_id = match note {
+            // the next deletion of the cloze entry the last card was written for
+            Some((entry_id, note_id)) if entry_id == entry.get_id() => note_id,
+            _ => {
+                let note_id = card_id - 1;
+                let guid = entry.id.clone().unwrap_or_else(|| entry.get_id().to_string());
+                let tags = match card.tags {
+                    Some(ref tags) if !tags.is_empty() => format!(" {} ", tags.join(" ")),
+                    _ => String::new(),
+                };
+                let (model, front, back, sort_field) = if card.is_cloze() {
+                    (cloze_model_id, escape_html(&card.content), card_back(card, &card.name), &card.content)
+                } else {
+                    (model_id, escape_html(&card.name), card_back(card, &card.content), &card.name)
+                };
+                let fields = format!("{}{}{}", front, FIELD_SEPARATOR, back);
+                connection.execute(
+                    "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+                    params![note_id, guid, model, now_s, tags, fields, sort_field, field_checksum(sort_field)],
+                )?;
+                note = Some((entry.get_id(), note_id));
+                note_id
+            }
+        };
+        // cloze cards are numbered from 0 for `c1`
+        let ord = match card.variant {
+            CardVariant::Cloze(index) => index.saturating_sub(1),
+            _ => 0,
+        };
+
+        let (card_type, mut queue, due, interval) = card_schedule(export_card, index as i64, collection_created, rollover_hour);
+        if export_card.item.as_ref().is_some_and(|item| item.ignored) {
+            queue = -1;
+        }
+        let data = match export_card.item.as_ref().and_then(|item| item.state.as_ref()) {
+            Some(MemoryState { memory: SchedulerMemory::Fsrs { stability, difficulty }, .. }) => {
+                json!({ "s": stability, "d": difficulty, "dr": retention }).to_string()
+            }
+            _ => String::new(),
+        };
+        let lapses = export_card.reviews.iter()
+            .filter(|review| review.is_review() && review.rating == Difficulty::Again)
+            .count();
+        connection.execute(
+            "INSERT INTO cards VALUES (?1, ?2, ?3, ?4, ?5, -1, ?6, ?7, ?8, ?9, 2500, ?10, ?11, 0, 0, 0, 0, ?12)",
+            params![card_id, note_id, deck_id, ord, now_s, card_type, queue, due, interval,
+                export_card.reviews.len(), lapses, data],
+        )?;
+
+        for review in &export_card.reviews {
+            let mut revlog_id = review.reviewed_at.timestamp_millis();
+            while !revlog_ids.insert(revlog_id) {
+                revlog_id += 1;
+            }
+            let review_type = match review.step {
+                Some(LearningPhase::Relearning(_)) => 2,
+                _ if review.is_review() => 1,
+                _ => 0,
+            };
+            connection.execute(
+                "INSERT INTO revlog VALUES (?1, ?2, -1, ?3, 0, 0, 0, ?4, ?5)",
+                params![revlog_id, card_id, review.rating.rating(), review.time_spent.min(i64::MAX as u64) as i64, review_type],
+            )?;
+        }
+    }
+    connection.close().map_err(|(_, error)| error)?;
+
+    let mut package = zip::ZipWriter::new(std::fs::File::create(output)?);
+    let options = SimpleFileOptions::default();
+    package.start_file("collection.anki2", options)?;
+    package.write_all(&std::fs::read(collection.path())?)?;
+    package.start_file("media", options)?;
+    package.write_all(b"{}")?;
+    package.finish()?;
+    Ok(())
+}
+
+/// Write the cards of the deck in `dir` to an Anki package and return the number of cards.
+pub async fn export(dir: &Path, deck: &Deck, loaded: &LoadedCards, output: &Path, options: &AnkiExportOptions) -> Result<usize> {
+    let mut lock_items: HashMap<_, _> = HashMap::new();
+    let mut reviews: HashMap<_, Vec<ReviewLogEntry>> = HashMap::new();
+    if options.with_scheduling {
+        lock_items = read_lock_file(dir).await?.into_iter().map(|item| (item.get_id(), item)).collect();
+        for entry in read_review_log(dir).await? {
+            reviews.entry(entry.get_id()).or_default().push(entry);
+        }
+    }
+    // cloze entries keep a card per deletion, other notes are exported with a single card,
+    // scheduled like the first card of the entry
+    let mut entries = HashSet::new();
+    let cards: Vec<_> = loaded.order.iter()
+        .filter(|id| loaded.cards[id].is_cloze() || entries.insert(loaded.cards[id].entry().get_id()))
+        .map(|id| ExportCard {
+            card: if loaded.cards[id].is_cloze() { loaded.cards[id].clone() } else { loaded.cards[id].entry() },
+            item: lock_items.remove(id),
+            reviews: reviews.remove(id).unwrap_or_default(),
+        }).collect();
+    let count = cards.len();
+    let retention = deck.fsrs_option.as_ref().map_or(default_retention(), |option| option.retention);
+    let rollover_hour = deck.day_rollover_hour;
+    let deck_name = deck.name.clone();
+    let output = output.to_path_buf();
+    tokio::task::spawn_blocking(move || write_package(&output, &deck_name, retention, rollover_hour, &cards, Utc::now())).await??;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use rusqlite::Connection;
+    use crate::interop::anki_import::{extract_collection, read_package};
+    use crate::core::learning::{Difficulty, LearningSteps};
+    use crate::core::scheduler::for_deck;
+    use crate::repository::deck::{CardDirection, CardItem, CardType, CardVariant, Deck, LearningStep};
+    use crate::repository::lock::LockItem;
+    use crate::repository::review_log::ReviewLogEntry;
+    use super::{write_package, ExportCard};
+
+    #[test]
+    fn test_export_can_be_imported() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("deck.apkg");
+        let cards = vec![ExportCard {
+            card: CardItem {
+                id: Some("card-1".to_string()),
+                name: "猫 & 犬".to_string(),
+                glance: None,
+                content: "cat\nand dog".to_string(),
+                tags: Some(vec!["animal".to_string()]),
+                direction: None,
+                card_type: None,
+                variant: CardVariant::Forward,
+            },
+            item: None,
+            reviews: Vec::new(),
+        }];
+        write_package(&output, "Animals", 0.9, 4, &cards, Utc::now()).unwrap();
+
+        let notes = read_package(&output).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].guid, "card-1");
+        assert_eq!(notes[0].fields, vec!["猫 &amp; 犬", "cat<br>and dog"]);
+        assert_eq!(notes[0].tags, vec!["animal"]);
+    }
+
+    #[test]
+    fn test_export_cloze_note() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("deck.apkg");
+        let entry = CardItem {
+            id: None,
+            name: "Capitals".to_string(),
+            glance: None,
+            content: "{{c1::Paris}} is in {{c2::France}}".to_string(),
+            tags: None,
+            direction: None,
+            card_type: Some(CardType::Cloze),
+            variant: CardVariant::Forward,
+        };
+        let cards: Vec<_> = entry.variant_cards(CardDirection::Forward).into_iter()
+            .map(|card| ExportCard { card, item: None, reviews: Vec::new() })
+            .collect();
+        assert_eq!(cards.len(), 2);
+        write_package(&output, "Geography", 0.9, 4, &cards, Utc::now()).unwrap();
+
+        let notes = read_package(&output).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].fields, vec!["{{c1::Paris}} is in {{c2::France}}", "Capitals"]);
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&output).unwrap()).unwrap();
+        let collection = extract_collection(&mut archive).unwrap();
+        let connection = Connection::open(collection.path()).unwrap();
+        let ords: Vec<u32> = connection.prepare("SELECT ord FROM cards ORDER BY ord").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(ords, vec![0, 1]);
+        let models: serde_json::Value = connection.query_row("SELECT models FROM col", [], |row| row.get::<_, String>(0))
+            .map(|models| serde_json::from_str(&models).unwrap()).unwrap();
+        let model_id: i64 = connection.query_row("SELECT mid FROM notes", [], |row| row.get(0)).unwrap();
+        assert_eq!(models[model_id.to_string()]["type"], 1);
+    }
+
+    #[test]
+    fn test_export_review_types() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("deck.apkg");
+        let mut deck = Deck::new("deck".to_string(), Vec::new());
+        deck.learning_steps = ["1m", "10m"].map(|step| LearningStep::try_from(step.to_string()).unwrap()).into();
+        deck.relearning_steps = vec![LearningStep::try_from("10m".to_string()).unwrap()];
+        let scheduler = for_deck(&deck).unwrap();
+        let steps = LearningSteps::for_deck(&deck);
+        let card = CardItem {
+            id: None,
+            name: "猫".to_string(),
+            glance: None,
+            content: "cat".to_string(),
+            tags: None,
+            direction: None,
+            card_type: None,
+            variant: CardVariant::Forward,
+        };
+        let mut item = LockItem::new_from_card(&card);
+        let mut reviews = Vec::new();
+        // new card, learning step, review that is forgotten, relearning step
+        for (day, rating) in [(0, Difficulty::Good), (0, Difficulty::Good), (3, Difficulty::Again), (3, Difficulty::Good)] {
+            let previous = item.clone();
+            let now = Utc::now() - chrono::Duration::days(10 - day);
+            let elapsed_days = item.next_state(scheduler.as_ref(), &steps, rating, &now, 4);
+            reviews.push(ReviewLogEntry::new(&previous, &item, rating, elapsed_days, std::time::Duration::ZERO));
+        }
+        let cards = vec![ExportCard { card, item: Some(item), reviews }];
+        write_package(&output, "Animals", 0.9, 4, &cards, Utc::now()).unwrap();
+
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(&output).unwrap()).unwrap();
+        let collection = extract_collection(&mut archive).unwrap();
+        let connection = Connection::open(collection.path()).unwrap();
+        let types: Vec<u32> = connection.prepare("SELECT type FROM revlog ORDER BY id").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+        assert_eq!(types, vec![0, 0, 1, 2]);
+        let lapses: u32 = connection.query_row("SELECT lapses FROM cards", [], |row| row.get(0)).unwrap();
+        assert_eq!(lapses, 1);
+    }
+}
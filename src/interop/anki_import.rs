@@ -0,0 +1,225 @@
+use std::io::Read;
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use rusqlite::Connection;
+use tempfile::NamedTempFile;
+use crate::core::deck_loader::{find_deck_meta_file, write_cards, write_deck_meta_file, write_lock_file, write_review_log};
+use crate::core::learning::{Difficulty, LearningSteps};
+use crate::core::scheduler::{self, Scheduler};
+use crate::repository::deck::{CardItem, CardVariant, Deck};
+use crate::repository::lock::LockItem;
+use crate::repository::review_log::ReviewLogEntry;
+
+/// Anki separates the fields of a note with the unit separator.
+pub(crate) const FIELD_SEPARATOR: char = '\x1f';
+const CARD_FILE: &str = "cards_1.yml";
+
+pub struct AnkiImportOptions {
+    pub deck_name: String,
+    /// Note field (starting at 1) used as card name.
+    pub name_field: usize,
+    pub content_field: usize,
+    pub glance_field: Option<usize>,
+    /// Convert the Anki review history into the lock file and review log.
+    pub with_history: bool,
+}
+
+pub struct AnkiImportReport {
+    pub cards: usize,
+    pub skipped: usize,
+    pub reviewed: usize,
+}
+
+struct AnkiReview {
+    time: DateTime<Utc>,
+    ease: u32,
+    duration_ms: u64,
+}
+
+pub(super) struct AnkiNote {
+    pub guid: String,
+    pub fields: Vec<String>,
+    pub tags: Vec<String>,
+    reviews: Vec<AnkiReview>,
+}
+
+/// Newer packages store a zstd-compressed `collection.anki21b`, older ones plain SQLite files.
+pub(super) fn extract_collection<R: Read + std::io::Seek>(archive: &mut zip::ZipArchive<R>) -> Result<NamedTempFile> {
+    let mut collection = NamedTempFile::new()?;
+    if let Ok(mut entry) = archive.by_name("collection.anki21b") {
+        zstd::stream::copy_decode(&mut entry, &mut collection)?;
+        return Ok(collection);
+    }
+    for name in ["collection.anki21", "collection.anki2"] {
+        if let Ok(mut entry) = archive.by_name(name) {
+            std::io::copy(&mut entry, &mut collection)?;
+            return Ok(collection);
+        }
+    }
+    bail!("No Anki collection found in the package")
+}
+
+fn read_notes(connection: &Connection) -> Result<Vec<AnkiNote>> {
+    let mut note_query = connection.prepare("SELECT id, guid, flds, tags FROM notes ORDER BY id")?;
+    let mut card_query = connection.prepare("SELECT id FROM cards WHERE nid = ?1 ORDER BY ord LIMIT 1")?;
+    // manual rescheduling and filtered deck reviews are not answers
+    let mut review_query = connection.prepare(
+        "SELECT id, ease, time FROM revlog WHERE cid = ?1 AND ease BETWEEN 1 AND 4 AND type IN (0, 1, 2) ORDER BY id"
+    )?;
+    let rows = note_query.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
+    })?;
+    let mut notes = Vec::new();
+    for row in rows {
+        let (note_id, guid, fields, tags) = row?;
+        let card_id: Option<i64> = card_query.query_map([note_id], |row| row.get(0))?.next().transpose()?;
+        let mut reviews = Vec::new();
+        if let Some(card_id) = card_id {
+            let rows = review_query.query_map([card_id], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, u32>(1)?, row.get::<_, i64>(2)?))
+            })?;
+            for row in rows {
+                let (review_id, ease, duration_ms) = row?;
+                let Some(time) = DateTime::from_timestamp_millis(review_id) else {
+                    continue;
+                };
+                reviews.push(AnkiReview { time, ease, duration_ms: duration_ms.max(0) as u64 });
+            }
+        }
+        notes.push(AnkiNote {
+            guid,
+            fields: fields.split(FIELD_SEPARATOR).map(str::to_string).collect(),
+            tags: tags.split_whitespace().map(str::to_string).collect(),
+            reviews,
+        });
+    }
+    Ok(notes)
+}
+
+pub(super) fn read_package(path: &Path) -> Result<Vec<AnkiNote>> {
+    let file = std::fs::File::open(path)?;
+    let mut archive = zip::ZipArchive::new(file)
+        .with_context(|| format!("{} is not an Anki package", path.display()))?;
+    let collection = extract_collection(&mut archive)?;
+    let connection = Connection::open(collection.path())?;
+    read_notes(&connection)
+}
+
+/// Turn the HTML of an Anki field into plain text.
+fn strip_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut tag = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let tag_name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/')
+                    .next().unwrap_or_default().to_ascii_lowercase();
+                if matches!(tag_name.as_str(), "br" | "div" | "p" | "li") {
+                    text.push('\n');
+                }
+            }
+            _ if in_tag => tag.push(c),
+            _ => text.push(c),
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    let lines: Vec<_> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    lines.join("\n")
+}
+
+fn note_field(note: &AnkiNote, field: usize) -> Option<String> {
+    let value = strip_html(note.fields.get(field.checked_sub(1)?)?);
+    if value.is_empty() { None } else { Some(value) }
+}
+
+/// Replay the Anki answers of a card through the scheduler to rebuild its memory state and review log.
+fn replay_history(card: &CardItem, reviews: &[AnkiReview], scheduler: &dyn Scheduler, deck: &Deck) -> (LockItem, Vec<ReviewLogEntry>) {
+    let mut item = LockItem::new_from_card(card);
+    let steps = LearningSteps::for_deck(deck);
+    let mut entries = Vec::new();
+    for review in reviews {
+        let Some(rating) = Difficulty::from_rating(review.ease) else {
+            continue;
+        };
+        let previous = item.clone();
+        let reviewed_at = review.time.with_timezone(&Local);
+        let elapsed_days = item.next_state(scheduler, &steps, rating, &reviewed_at, deck.day_rollover_hour);
+        let time_spent = std::time::Duration::from_millis(review.duration_ms);
+        entries.push(ReviewLogEntry::new(&previous, &item, rating, elapsed_days, time_spent));
+    }
+    (item, entries)
+}
+
+/// Create a deck in `dir` from the notes of an Anki `.apkg` or `.colpkg` package.
+pub async fn import(package: &Path, dir: &Path, options: &AnkiImportOptions) -> Result<AnkiImportReport> {
+    tokio::fs::create_dir_all(dir).await?;
+    if find_deck_meta_file(dir).await?.is_some() {
+        bail!("{} already contains a deck", dir.display());
+    }
+    let package = package.to_path_buf();
+    let notes = tokio::task::spawn_blocking(move || read_package(&package)).await??;
+
+    let deck = Deck::new(options.deck_name.clone(), vec![CARD_FILE.to_string()]);
+    let scheduler = scheduler::for_deck(&deck)?;
+    let mut report = AnkiImportReport { cards: 0, skipped: 0, reviewed: 0 };
+    let mut cards = Vec::new();
+    let mut lock_list = Vec::new();
+    let mut review_log = Vec::new();
+    for note in notes {
+        let (Some(name), Some(content)) = (note_field(&note, options.name_field), note_field(&note, options.content_field)) else {
+            report.skipped += 1;
+            continue;
+        };
+        let card = CardItem {
+            id: Some(note.guid.clone()),
+            name,
+            glance: options.glance_field.and_then(|field| note_field(&note, field)),
+            content,
+            tags: if note.tags.is_empty() { None } else { Some(note.tags.clone()) },
+            direction: None,
+            card_type: None,
+            variant: CardVariant::Forward,
+        };
+        if options.with_history {
+            let (item, entries) = replay_history(&card, &note.reviews, scheduler.as_ref(), &deck);
+            if item.state.is_some() {
+                report.reviewed += 1;
+            }
+            lock_list.push(item);
+            review_log.extend(entries);
+        }
+        cards.push(card);
+    }
+    report.cards = cards.len();
+
+    write_cards(&dir.join(CARD_FILE), &cards).await?;
+    write_deck_meta_file(dir, &deck).await?;
+    if options.with_history {
+        review_log.sort_by_key(|entry| entry.reviewed_at);
+        write_lock_file(dir, &lock_list).await?;
+        write_review_log(dir, &review_log).await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_strip_html() {
+        let html = "<div>Tokyo&nbsp;is <b>big</b></div><div>&lt;capital&gt;<br/>of Japan</div>";
+        assert_eq!(super::strip_html(html), "Tokyo is big\n<capital>\nof Japan");
+    }
+}
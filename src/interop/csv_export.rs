@@ -0,0 +1,69 @@
+use std::collections::HashSet;
+use std::path::Path;
+use anyhow::Result;
+use crate::core::deck_loader::LoadedCards;
+use crate::interop::csv_import::{CsvColumn, CsvFormat};
+use crate::repository::deck::CardItem;
+
+pub struct CsvExportOptions {
+    pub format: CsvFormat,
+    pub columns: Vec<CsvColumn>,
+    pub header: bool,
+}
+
+fn card_field(card: &CardItem, column: CsvColumn, tag_separator: &str) -> String {
+    match column {
+        CsvColumn::Id => card.id.clone().unwrap_or_default(),
+        CsvColumn::Name => card.name.clone(),
+        CsvColumn::Glance => card.glance.clone().unwrap_or_default(),
+        CsvColumn::Content => card.content.clone(),
+        CsvColumn::Tags => card.tags.as_ref().map(|tags| tags.join(tag_separator)).unwrap_or_default(),
+        CsvColumn::Skip => String::new(),
+    }
+}
+
+/// Write the cards of a deck to a CSV or TSV file in card file order and return the number of cards.
+pub async fn export(loaded: &LoadedCards, output: &Path, options: &CsvExportOptions) -> Result<usize> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.format.delimiter)
+        .quote(options.format.quote)
+        .from_writer(Vec::new());
+    if options.header {
+        writer.write_record(options.columns.iter().map(CsvColumn::label))?;
+    }
+    // reverse and cloze cards are generated from an entry, which is written once
+    let mut entries = HashSet::new();
+    let cards: Vec<_> = loaded.order.iter()
+        .map(|id| loaded.cards[id].entry())
+        .filter(|card| entries.insert(card.get_id()))
+        .collect();
+    for card in &cards {
+        writer.write_record(options.columns.iter().map(|column| card_field(card, *column, &options.format.tag_separator)))?;
+    }
+    tokio::fs::write(output, writer.into_inner()?).await?;
+    Ok(cards.len())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::deck_loader::{create_or_update_lock_file, read_deck_meta_file};
+    use crate::interop::csv_import::{CsvFormat, DEFAULT_COLUMNS};
+    use super::{export, CsvExportOptions};
+
+    #[tokio::test]
+    async fn test_export_reverse_only_deck() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("deck.yaml"), "name: deck\ncard_files: [cards.yaml]\ndirection: reverse\n").unwrap();
+        std::fs::write(dir.path().join("cards.yaml"), "- name: 猫\n  content: cat\n- name: 犬\n  content: dog\n  direction: both\n").unwrap();
+        let deck = read_deck_meta_file(dir.path()).await.unwrap();
+        let loaded = create_or_update_lock_file(dir.path(), &deck).await.unwrap();
+        let output = dir.path().join("cards.csv");
+        let options = CsvExportOptions {
+            format: CsvFormat::for_path(&output),
+            columns: vec![DEFAULT_COLUMNS[0], DEFAULT_COLUMNS[1]],
+            header: false,
+        };
+        assert_eq!(export(&loaded, &output, &options).await.unwrap(), 2);
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "猫,cat\n犬,dog\n");
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::path::Path;
+use anyhow::{bail, Context, Result};
+use crate::core::deck_loader::{append_cards, create_or_update_lock_file, find_deck_meta_file, read_cards, read_deck_meta_file, resolve_card_files,
+                               update_deck_meta_key, write_deck_meta_file};
+use crate::repository::deck::{CardItem, CardVariant, Deck};
+use crate::repository::lock::CardItemIdentify;
+
+/// Columns used when the file has no header and no mapping is given.
+pub const DEFAULT_COLUMNS: [CsvColumn; 5] = [CsvColumn::Name, CsvColumn::Content, CsvColumn::Glance, CsvColumn::Tags, CsvColumn::Id];
+const CARD_FILE: &str = "cards_1.yml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvColumn {
+    Id,
+    Name,
+    Glance,
+    Content,
+    Tags,
+    /// A column that is not part of the card.
+    Skip,
+}
+
+impl CsvColumn {
+    pub fn parse(column: &str) -> Option<CsvColumn> {
+        match column.trim().to_lowercase().as_str() {
+            "id" => Some(CsvColumn::Id),
+            "name" => Some(CsvColumn::Name),
+            "glance" => Some(CsvColumn::Glance),
+            "content" => Some(CsvColumn::Content),
+            "tags" => Some(CsvColumn::Tags),
+            "_" | "skip" => Some(CsvColumn::Skip),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CsvColumn::Id => "id",
+            CsvColumn::Name => "name",
+            CsvColumn::Glance => "glance",
+            CsvColumn::Content => "content",
+            CsvColumn::Tags => "tags",
+            CsvColumn::Skip => "_",
+        }
+    }
+}
+
+/// Parse a column mapping like `name,content,_,tags`.
+pub fn parse_columns(spec: &str) -> Result<Vec<CsvColumn>> {
+    spec.split(',')
+        .map(|column| CsvColumn::parse(column).with_context(|| format!("Unknown column \"{}\"", column.trim())))
+        .collect()
+}
+
+pub struct CsvFormat {
+    pub delimiter: u8,
+    pub quote: u8,
+    pub tag_separator: String,
+}
+
+impl CsvFormat {
+    /// Tab separated for `.tsv` files, comma separated otherwise.
+    pub fn for_path(path: &Path) -> CsvFormat {
+        let tsv = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("tsv"));
+        CsvFormat {
+            delimiter: if tsv { b'\t' } else { b',' },
+            quote: b'"',
+            tag_separator: " ".to_string(),
+        }
+    }
+}
+
+pub struct CsvImportOptions {
+    pub format: CsvFormat,
+    pub columns: Option<Vec<CsvColumn>>,
+    /// Whether the first row is a header, detected from its cells when `None`.
+    pub header: Option<bool>,
+    /// Card file of the deck the cards are appended to.
+    pub card_file: Option<String>,
+    /// Name of the deck created when the directory has none.
+    pub deck_name: String,
+}
+
+pub struct CsvImportReport {
+    pub cards: usize,
+    pub skipped: usize,
+    pub card_file: String,
+    pub duplicates: Vec<(String, CardItemIdentify)>,
+}
+
+fn is_header(record: &csv::StringRecord) -> bool {
+    let columns: Option<Vec<_>> = record.iter().map(CsvColumn::parse).collect();
+    columns.is_some_and(|columns| columns.contains(&CsvColumn::Name))
+}
+
+fn record_card(record: &csv::StringRecord, columns: &[CsvColumn], tag_separator: &str) -> Option<CardItem> {
+    let field = |column: CsvColumn| {
+        let index = columns.iter().position(|c| *c == column)?;
+        let value = record.get(index)?.trim();
+        if value.is_empty() { None } else { Some(value.to_string()) }
+    };
+    let tags: Vec<String> = field(CsvColumn::Tags).iter()
+        .flat_map(|tags| tags.split(tag_separator))
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    Some(CardItem {
+        id: field(CsvColumn::Id),
+        name: field(CsvColumn::Name)?,
+        glance: field(CsvColumn::Glance),
+        content: field(CsvColumn::Content)?,
+        tags: if tags.is_empty() { None } else { Some(tags) },
+        direction: None,
+        card_type: None,
+        variant: CardVariant::Forward,
+    })
+}
+
+/// Read cards from CSV text, returning them with the number of rows lacking a name or content.
+fn parse_records(text: &str, options: &CsvImportOptions) -> Result<(Vec<CardItem>, usize)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(options.format.delimiter)
+        .quote(options.format.quote)
+        .from_reader(text.as_bytes());
+    let mut records = reader.records().peekable();
+    let header = match records.peek() {
+        Some(Ok(first)) => options.header.unwrap_or_else(|| is_header(first)),
+        _ => false,
+    };
+    let mut columns = options.columns.clone();
+    if header {
+        let first = records.next().transpose()?.unwrap_or_default();
+        if columns.is_none() {
+            columns = Some(first.iter().map(|cell| CsvColumn::parse(cell).unwrap_or(CsvColumn::Skip)).collect());
+        }
+    }
+    let columns = columns.unwrap_or_else(|| DEFAULT_COLUMNS.to_vec());
+    if !columns.contains(&CsvColumn::Name) || !columns.contains(&CsvColumn::Content) {
+        bail!("The column mapping needs a name and a content column");
+    }
+
+    let mut cards = Vec::new();
+    let mut skipped = 0;
+    for record in records {
+        match record_card(&record?, &columns, &options.format.tag_separator) {
+            Some(card) => cards.push(card),
+            None => skipped += 1,
+        }
+    }
+    Ok((cards, skipped))
+}
+
+/// Append the cards of a CSV or TSV file to a card file of the deck in `dir`, creating both when missing.
+pub async fn import(file: &Path, dir: &Path, options: &CsvImportOptions) -> Result<CsvImportReport> {
+    let text = tokio::fs::read_to_string(file).await?;
+    let (cards, skipped) = parse_records(&text, options)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+
+    tokio::fs::create_dir_all(dir).await?;
+    let has_deck = find_deck_meta_file(dir).await?.is_some();
+    let (mut deck, card_files, mut known) = if has_deck {
+        let deck = read_deck_meta_file(dir).await?;
+        let loaded = create_or_update_lock_file(dir, &deck).await?;
+        let card_files = resolve_card_files(dir, &deck).await?;
+        (deck, card_files, loaded.cards.into_keys().collect())
+    } else {
+        (Deck::new(options.deck_name.clone(), Vec::new()), Vec::new(), HashSet::new())
+    };
+    let card_file = options.card_file.clone()
+        .or_else(|| card_files.last().cloned())
+        .unwrap_or_else(|| CARD_FILE.to_string());
+    let card_path = dir.join(&card_file);
+    let card_file_exists = tokio::fs::try_exists(&card_path).await?;
+    if card_file_exists {
+        known.extend(read_cards(&card_path).await?.iter().map(CardItem::get_id));
+    }
+
+    let mut report = CsvImportReport { cards: 0, skipped, card_file: card_file.clone(), duplicates: Vec::new() };
+    let mut new_cards = Vec::new();
+    for card in cards {
+        let id = card.get_id();
+        if known.insert(id) {
+            new_cards.push(card);
+            report.cards += 1;
+        } else {
+            report.duplicates.push((card.name, id));
+        }
+    }
+
+    // the user's files are only added to, the existing cards and settings keep their comments and layout
+    if !new_cards.is_empty() || !card_file_exists {
+        append_cards(&card_path, &new_cards).await?;
+    }
+    if !card_files.contains(&card_file) {
+        deck.card_files.push(card_file);
+        if has_deck {
+            update_deck_meta_key(dir, "card_files", &deck.card_files).await?;
+        } else {
+            write_deck_meta_file(dir, &deck).await?;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{import, parse_columns, parse_records, CsvColumn, CsvFormat, CsvImportOptions};
+
+    fn options(columns: Option<Vec<CsvColumn>>, header: Option<bool>) -> CsvImportOptions {
+        CsvImportOptions {
+            format: CsvFormat { delimiter: b',', quote: b'"', tag_separator: ";".to_string() },
+            columns,
+            header,
+            card_file: None,
+            deck_name: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let text = "Tags,Name,Content\n\"n5; noun\",猫,\"cat, the animal\"\n,犬,\nverb,食べる,eat\n";
+        let (cards, skipped) = parse_records(text, &options(None, None)).unwrap();
+        assert_eq!(skipped, 1);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].name, "猫");
+        assert_eq!(cards[0].content, "cat, the animal");
+        assert_eq!(cards[0].tags, Some(vec!["n5".to_string(), "noun".to_string()]));
+        assert_eq!(cards[1].glance, None);
+
+        let columns = parse_columns("_, name, content").unwrap();
+        let (cards, _) = parse_records("1,name,content\n2,猫,cat\n", &options(Some(columns), Some(false))).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].name, "name");
+        assert!(parse_columns("name,answer").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_appends_to_user_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let deck_yaml = "# Spanish\nname: test\ncard_files: [cards.yml]\nnew_per_day: 5 # slowly\n";
+        let cards_yml = "# nouns\n- name: gato\n  content: cat # the animal\n";
+        std::fs::write(dir.path().join("deck.yaml"), deck_yaml).unwrap();
+        std::fs::write(dir.path().join("cards.yml"), cards_yml).unwrap();
+        let csv = dir.path().join("import.csv");
+        std::fs::write(&csv, "name,content\ngato,cat\nperro,dog\n").unwrap();
+
+        let report = import(&csv, dir.path(), &options(None, None)).await.unwrap();
+        assert_eq!(report.cards, 1);
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(std::fs::read_to_string(dir.path().join("cards.yml")).unwrap(), format!("{}- name: perro\n  content: dog\n", cards_yml));
+        assert_eq!(std::fs::read_to_string(dir.path().join("deck.yaml")).unwrap(), deck_yaml);
+
+        let options = CsvImportOptions { card_file: Some("verbs.yml".to_string()), ..options(None, None) };
+        std::fs::write(&csv, "name,content\ncomer,eat\n").unwrap();
+        import(&csv, dir.path(), &options).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("deck.yaml")).unwrap(),
+            "# Spanish\nname: test\ncard_files:\n- cards.yml\n- verbs.yml\nnew_per_day: 5 # slowly\n",
+        );
+    }
+}
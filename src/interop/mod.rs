@@ -0,0 +1,40 @@
+pub mod anki_export;
+pub mod anki_import;
+pub mod csv_export;
+pub mod csv_import;
+
+use std::path::{Path, PathBuf};
+
+/// Characters some file systems do not allow in file names.
+const RESERVED_CHARACTERS: &str = "/\\:*?\"<>|";
+
+/// The file an export of the deck in `dir` is written to when no output is given, named after the deck.
+///
+/// Characters a file name cannot hold are replaced, and a deck without a usable name falls back to its directory name.
+pub fn default_output(deck_name: &str, dir: &Path, extension: &str) -> PathBuf {
+    let name: String = deck_name.chars()
+        .map(|c| if c.is_control() || RESERVED_CHARACTERS.contains(c) { '_' } else { c })
+        .collect();
+    let name = name.trim().trim_matches('.');
+    let name = if name.is_empty() {
+        dir.file_name().map_or("deck".to_string(), |dir_name| dir_name.to_string_lossy().to_string())
+    } else {
+        name.to_string()
+    };
+    PathBuf::from(format!("{}.{}", name, extension))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+    use super::default_output;
+
+    #[test]
+    fn test_default_output_is_a_file_name() {
+        let dir = Path::new("/decks/spanish");
+        assert_eq!(default_output("Spanish", dir, "apkg"), PathBuf::from("Spanish.apkg"));
+        assert_eq!(default_output("Spanish/Verbs: ser", dir, "csv"), PathBuf::from("Spanish_Verbs_ ser.csv"));
+        assert_eq!(default_output("../..", dir, "apkg"), PathBuf::from("_.apkg"));
+        assert_eq!(default_output(" ", dir, "apkg"), PathBuf::from("spanish.apkg"));
+    }
+}
@@ -1,39 +1,108 @@
-use crate::cli::{Cli, Commands};
+use crate::cli::{Cli, Commands, CsvFormatArgs, ExportCommands, ImportCommands, SelectArgs};
+use crate::interop::anki_export::{self, AnkiExportOptions};
+use crate::interop::anki_import::{self, AnkiImportOptions};
+use crate::interop::csv_export::{self, CsvExportOptions};
+use crate::interop::csv_import::{self, CsvColumn, CsvFormat, CsvImportOptions};
 use crate::core::deck_loader::create_or_update_lock_file;
-use crate::core::{deck_initial, deck_loader, learning, RunningCore};
-use crate::repository::deck::CardItem;
-use anyhow::Result;
+use crate::core::deck_loader::LoadedCards;
+use crate::core::manage::CardSelector;
+use crate::core::tag_filter::{StudyFilter, TagExpr};
+use crate::core::collection::{self, Collection};
+use crate::core::{check, deck_initial, deck_loader, manage, migrate, optimizer, stats, RunningCore};
+use crate::repository::lock::{LockMigration, MigrationKind};
+use anyhow::{bail, Result};
 use clap::Parser;
-use std::fmt::Display;
-use std::path::{Path, PathBuf};
+use chrono::Local;
+use std::path::Path;
 use std::process;
-use tokio::io::{self, AsyncBufReadExt, BufReader};
 
 mod repository;
 mod core;
 mod cli;
+mod ui;
+mod interop;
 
-const COMMAND_HINT_1: &str = "(q: quit | r: reveal | i: ignore)";
-const COMMAND_HINT_2: &str = "(q: quit | a: easy, s: good, d: hard, f: again | i: ignore)";
-const EMPTY_CARD: &str = "All cards are done!";
+async fn ensure_deck(dir_path: &Path) -> Result<()> {
+    let meta_file = deck_loader::find_deck_meta_file(dir_path).await?;
+    if meta_file.is_none() {
+        eprintln!("Deck metadata file (deck.yaml, deck.json or deck.toml) not found");
+        process::exit(1);
+    }
+    Ok(())
+}
 
-impl Display for CardItem {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let name = self.name.as_str();
-        let glance = self.glance.as_ref().map(|s| format!("glance: {}\n", s)).unwrap_or(String::new());
-        let content = self.content.as_str();
-        let tags = match self.tags.as_ref() {
-            Some(tags) => tags.join(", "),
-            None => String::new()
+fn report_migrations(migrations: &[LockMigration]) {
+    for migration in migrations {
+        let reason = match migration.kind {
+            MigrationKind::IdAssigned => "explicit id assigned",
+            MigrationKind::ContentChanged => "content changed",
+            MigrationKind::Renamed => "renamed",
         };
-        write!(f, "{}\n{}{}\n{}", name, glance, content, tags)
+        println!("Kept progress of \"{}\" ({})", migration.card_name, reason);
+    }
+}
+
+async fn load_cards(dir_path: &Path) -> Result<LoadedCards> {
+    ensure_deck(dir_path).await?;
+    let deck = deck_loader::read_deck_meta_file(dir_path).await?;
+    let loaded = create_or_update_lock_file(dir_path, &deck).await?;
+    report_migrations(&loaded.migrations);
+    Ok(loaded)
+}
+
+fn csv_format(format_args: &CsvFormatArgs, file: &Path) -> Result<(CsvFormat, Option<Vec<CsvColumn>>)> {
+    let mut format = CsvFormat::for_path(file);
+    if let Some(delimiter) = format_args.delimiter {
+        let Ok(delimiter) = u8::try_from(delimiter) else { bail!("The delimiter must be an ASCII character") };
+        format.delimiter = delimiter;
     }
+    let Ok(quote) = u8::try_from(format_args.quote) else { bail!("The quote must be an ASCII character") };
+    format.quote = quote;
+    format.tag_separator = format_args.tag_separator.clone();
+    let columns = format_args.columns.as_deref().map(csv_import::parse_columns).transpose()?;
+    Ok((format, columns))
 }
 
-fn partial_display(card_item: &CardItem) -> String {
-    let name = card_item.name.as_str();
-    let glance = card_item.glance.as_ref().map(|s| format!("glance: {}\n", s)).unwrap_or(String::new());
-    format!("{}\n{}", name, glance)
+/// Open every deck of the collection at `dir_path`, which may also be a single deck and its sub-decks.
+async fn open_collection(dir_path: &Path) -> Result<Collection> {
+    let (name, deck_dirs) = collection::discover_decks(dir_path).await?;
+    if deck_dirs.is_empty() {
+        ensure_deck(dir_path).await?;
+    }
+    let mut decks = Vec::new();
+    for deck_dir in deck_dirs {
+        ensure_deck(&deck_dir).await?;
+        let deck = deck_loader::read_deck_meta_file(&deck_dir).await?;
+        let loaded = create_or_update_lock_file(&deck_dir, &deck).await?;
+        report_migrations(&loaded.migrations);
+        decks.push(RunningCore::new(deck_dir, deck, loaded).await?);
+    }
+    // a deck with sub-decks names the whole tree
+    let root_dir = tokio::fs::canonicalize(dir_path).await.ok();
+    let name = match decks.first() {
+        Some(root) if root_dir.as_ref() == Some(&root.working_dir) => root.deck_name().to_string(),
+        _ => name,
+    };
+    Ok(Collection::new(name, decks))
+}
+
+async fn set_suspended(select_args: SelectArgs, suspended: bool) -> Result<()> {
+    let dir_path = select_args.dir.path()?;
+    let loaded = load_cards(&dir_path).await?;
+    let selector = CardSelector {
+        names: select_args.name,
+        tags: select_args.tag,
+        ids: select_args.id,
+    };
+    let changed = manage::set_suspended(&dir_path, &loaded, &selector, suspended).await?;
+    let action = if suspended { "Suspended" } else { "Unsuspended" };
+    for name in &changed {
+        println!("{}: {}", action, name);
+    }
+    if changed.is_empty() {
+        println!("No card changed");
+    }
+    Ok(())
 }
 
 #[tokio::main]
@@ -41,91 +110,182 @@ async fn main() -> Result<()> {
     let args = Cli::parse();
     match args.command {
         Commands::Init(dir_args) => {
-            let dir_path: PathBuf = if dir_args.dir == "." {
-                std::env::current_dir()?
-            } else { Path::new(&dir_args.dir).to_path_buf() };
+            let dir_path = dir_args.path()?;
             deck_initial::write_initial_deck(&dir_path).await?;
             println!("Deck initialized at {:?}", dir_path);
             Ok(())
         }
-        Commands::Start(dir_args) => {
-            let dir_path: PathBuf = if dir_args.dir == "." {
-                std::env::current_dir()?
-            } else { Path::new(&dir_args.dir).to_path_buf() };
-            let meta_file = deck_loader::find_deck_meta_file(&dir_path).await?;
-            if meta_file.is_none() {
-                eprintln!("Deck metadata file (deck.yaml) not found");
-                process::exit(1);
+        Commands::Start(start_args) => {
+            let filter = StudyFilter {
+                tags: start_args.tag.iter().map(|tag| TagExpr::parse(tag)).collect::<Result<_>>()?,
+                exclude_tags: start_args.exclude_tag,
+                files: start_args.file,
+            };
+            let dir_path = start_args.dir.path()?;
+            let mut collection = open_collection(&dir_path).await?;
+            if !filter.is_empty() {
+                collection.restrict(&filter)?;
             }
+            ui::session::run(&mut collection, start_args.typed).await
+        }
+        Commands::Optimize(dir_args) => {
+            let dir_path = dir_args.path()?;
+            ensure_deck(&dir_path).await?;
+            let report = optimizer::optimize(&dir_path).await?;
+            println!("Trained on {} review histories", report.items);
+            println!("Weights: {:?}", report.weights);
+            Ok(())
+        }
+        Commands::MigrateAlgorithm(migrate_args) => {
+            let dir_path = migrate_args.dir.path()?;
+            ensure_deck(&dir_path).await?;
             let deck = deck_loader::read_deck_meta_file(&dir_path).await?;
-            let cards = create_or_update_lock_file(&dir_path, &deck).await?;
-            let mut running_core = RunningCore::new(dir_path, deck, cards).await;
-            let stdin = io::stdin();
-            let mut reader = BufReader::new(stdin).lines();
-            'cards_loop: loop {
-                let new_one = running_core.random_on_time();
-                if new_one.is_none() {
-                    println!("{}", EMPTY_CARD);
-                    break;
-                }
-                let new_one = new_one.unwrap();
-                let id = new_one.get_id();
-                let card = running_core.cards.get(&id).unwrap();
-                println!("\n{}\n{}\n", COMMAND_HINT_1, partial_display(card));
-                'command_1_input_loop: loop {
-                    if let Ok(Some(line)) = reader.next_line().await {
-                        match line.as_str() {
-                            "q" => break 'cards_loop,
-                            "r" => break 'command_1_input_loop,
-                            "i" => {
-                                running_core.set_ignored(id).await?;
-                                println!("Card ignored");
-                                continue 'cards_loop;
-                            },
-                            _ => continue 'command_1_input_loop
-                        }
-                    } else {
-                        break 'cards_loop;
-                    }
-                }
-                println!("\n{}\n{}\n", COMMAND_HINT_2, card);
-                'command_2_input_loop: loop {
-                    if let Ok(Some(line)) = reader.next_line().await {
-                        match line.as_str() {
-                            "q" => break 'cards_loop,
-                            "a" => {
-                                running_core.next_state(id, learning::Difficulty::Easy).await?;
-                                println!("Card marked as easy");
-                                continue 'cards_loop;
-                            },
-                            "s" => {
-                                running_core.next_state(id, learning::Difficulty::Good).await?;
-                                println!("Card marked as good");
-                                continue 'cards_loop;
-                            },
-                            "d" => {
-                                running_core.next_state(id, learning::Difficulty::Hard).await?;
-                                println!("Card marked as hard");
-                                continue 'cards_loop;
-                            },
-                            "f" => {
-                                running_core.next_state(id, learning::Difficulty::Again).await?;
-                                println!("Card marked as again");
-                                continue 'cards_loop;
-                            },
-                            "i" => {
-                                running_core.set_ignored(id).await?;
-                                println!("Card ignored");
-                                continue 'cards_loop;
-                            },
-                            _ => continue 'command_2_input_loop
-                        }
-                    } else {
-                        break 'cards_loop;
-                    }
+            create_or_update_lock_file(&dir_path, &deck).await?;
+            let report = migrate::migrate_algorithm(&dir_path, migrate_args.to).await?;
+            println!("Switched from {} to {}", deck.algorithm.label(), migrate_args.to.label());
+            println!("{} cards replayed from the review log, {} cards converted", report.replayed, report.converted);
+            Ok(())
+        }
+        Commands::Stats(stats_args) => {
+            let dir_path = stats_args.dir.path()?;
+            ensure_deck(&dir_path).await?;
+            let deck = deck_loader::read_deck_meta_file(&dir_path).await?;
+            let loaded = create_or_update_lock_file(&dir_path, &deck).await?;
+            let deck_stats = stats::collect(&dir_path, &deck).await?;
+            if stats_args.json {
+                println!("{}", serde_json::to_string_pretty(&deck_stats)?);
+            } else {
+                report_migrations(&loaded.migrations);
+                print!("{}", deck_stats);
+            }
+            Ok(())
+        }
+        Commands::Suspend(select_args) => set_suspended(select_args, true).await,
+        Commands::Unsuspend(select_args) => set_suspended(select_args, false).await,
+        Commands::List(list_args) => {
+            let dir_path = list_args.dir.path()?;
+            let loaded = load_cards(&dir_path).await?;
+            let deck = deck_loader::read_deck_meta_file(&dir_path).await?;
+            let now = Local::now();
+            for (card, item) in manage::list_cards(&dir_path, &loaded).await? {
+                if list_args.ignored && !item.ignored {
+                    continue;
                 }
+                let status = if item.ignored {
+                    "ignored"
+                } else if item.state.is_none() {
+                    "new"
+                } else if item.filter_on_time(&now, deck.day_rollover_hour) {
+                    "due"
+                } else {
+                    "scheduled"
+                };
+                let id = card.id.clone().unwrap_or_else(|| item.get_id().to_string());
+                let direction = card.variant.label().map(|label| format!(" ({})", label)).unwrap_or_default();
+                println!("{:<36}  {:<9}  {}{}", id, status, card.name, direction);
+            }
+            Ok(())
+        }
+        Commands::Check(dir_args) => {
+            let dir_path = dir_args.path()?;
+            let diagnostics = check::check(&dir_path).await?;
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic);
+            }
+            if !diagnostics.is_empty() {
+                eprintln!("Found {} problems", diagnostics.len());
+                process::exit(1);
+            }
+            println!("No problems found");
+            Ok(())
+        }
+        Commands::Decks(dir_args) => {
+            let dir_path = dir_args.path()?;
+            let collection = open_collection(&dir_path).await?;
+            let root_dir = tokio::fs::canonicalize(&dir_path).await?;
+            println!("{:<32}  {:>5}  {:>5}  {:>6}  path", "deck", "due", "new", "total");
+            for deck in collection.decks() {
+                let progress = deck.progress();
+                let path = deck.working_dir.strip_prefix(&root_dir).unwrap_or(&deck.working_dir);
+                let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+                println!("{:<32}  {:>5}  {:>5}  {:>6}  {}", deck.deck_name(), progress.due, progress.new, deck.cards.len(), path.display());
+            }
+            let total = collection.progress();
+            println!("{:<32}  {:>5}  {:>5}", "total", total.due, total.new);
+            Ok(())
+        }
+        Commands::Import(ImportCommands::Anki(anki_args)) => {
+            let dir_path = anki_args.dir.path()?;
+            let deck_name = match anki_args.name {
+                Some(name) => name,
+                None => anki_args.file.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            };
+            let options = AnkiImportOptions {
+                deck_name,
+                name_field: anki_args.name_field,
+                content_field: anki_args.content_field,
+                glance_field: anki_args.glance_field,
+                with_history: anki_args.with_history,
+            };
+            let report = anki_import::import(&anki_args.file, &dir_path, &options).await?;
+            println!("Imported {} cards into {:?}", report.cards, dir_path);
+            if options.with_history {
+                println!("Kept review history of {} cards", report.reviewed);
             }
+            if report.skipped > 0 {
+                println!("Skipped {} notes with an empty name or content field", report.skipped);
+            }
+            Ok(())
+        }
+        Commands::Import(ImportCommands::Csv(csv_args)) => {
+            let dir_path = csv_args.dir.path()?;
+            let (format, columns) = csv_format(&csv_args.format, &csv_args.file)?;
+            let header = if csv_args.header {
+                Some(true)
+            } else if csv_args.no_header {
+                Some(false)
+            } else {
+                None
+            };
+            let deck_name = match csv_args.name {
+                Some(name) => name,
+                None => csv_args.file.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            };
+            let options = CsvImportOptions { format, columns, header, card_file: csv_args.into, deck_name };
+            let report = csv_import::import(&csv_args.file, &dir_path, &options).await?;
+            println!("Imported {} cards into {}", report.cards, report.card_file);
+            for (name, id) in &report.duplicates {
+                println!("Skipped duplicate \"{}\" ({})", name, id);
+            }
+            if report.skipped > 0 {
+                println!("Skipped {} rows with an empty name or content", report.skipped);
+            }
+            Ok(())
+        }
+        Commands::Export(ExportCommands::Anki(anki_args)) => {
+            let dir_path = anki_args.dir.path()?;
+            let loaded = load_cards(&dir_path).await?;
+            let deck = deck_loader::read_deck_meta_file(&dir_path).await?;
+            let output = anki_args.output.unwrap_or_else(|| interop::default_output(&deck.name, &dir_path, "apkg"));
+            let options = AnkiExportOptions { with_scheduling: anki_args.with_scheduling };
+            let count = anki_export::export(&dir_path, &deck, &loaded, &output, &options).await?;
+            println!("Exported {} cards to {:?}", count, output);
+            Ok(())
+        }
+        Commands::Export(ExportCommands::Csv(csv_args)) => {
+            let dir_path = csv_args.dir.path()?;
+            let loaded = load_cards(&dir_path).await?;
+            let deck = deck_loader::read_deck_meta_file(&dir_path).await?;
+            let output = csv_args.output.unwrap_or_else(|| interop::default_output(&deck.name, &dir_path, "csv"));
+            let (format, columns) = csv_format(&csv_args.format, &output)?;
+            let options = CsvExportOptions {
+                format,
+                columns: columns.unwrap_or_else(|| csv_import::DEFAULT_COLUMNS.to_vec()),
+                header: !csv_args.no_header,
+            };
+            let count = csv_export::export(&loaded, &output, &options).await?;
+            println!("Exported {} cards to {:?}", count, output);
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,106 @@
+/// A piece of a cloze card content like `The capital is {{c1::Tokyo::city}}.`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClozePart<'a> {
+    Text(&'a str),
+    Deletion {
+        index: u32,
+        answer: &'a str,
+        hint: Option<&'a str>,
+    },
+}
+
+/// Parse a `{{cN::answer}}` or `{{cN::answer::hint}}` marker at the start of `text`,
+/// returning the deletion and the length of the marker.
+fn parse_deletion(text: &str) -> Option<(ClozePart<'_>, usize)> {
+    let rest = text.strip_prefix("{{c")?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+    let index = rest[..digits].parse().ok()?;
+    let body_start = rest[digits..].strip_prefix("::")?;
+    let body_end = body_start.find("}}")?;
+    let body = &body_start[..body_end];
+    let (answer, hint) = match body.split_once("::") {
+        Some((answer, hint)) => (answer, Some(hint)),
+        None => (body, None),
+    };
+    let length = text.len() - body_start.len() + body_end + "}}".len();
+    Some((ClozePart::Deletion { index, answer, hint }, length))
+}
+
+pub fn parse(content: &str) -> Vec<ClozePart<'_>> {
+    let mut parts = Vec::new();
+    let mut text_start = 0;
+    let mut position = 0;
+    while let Some(found) = content[position..].find("{{c") {
+        let marker_start = position + found;
+        match parse_deletion(&content[marker_start..]) {
+            Some((deletion, length)) => {
+                if text_start < marker_start {
+                    parts.push(ClozePart::Text(&content[text_start..marker_start]));
+                }
+                parts.push(deletion);
+                position = marker_start + length;
+                text_start = position;
+            }
+            None => position = marker_start + "{{c".len(),
+        }
+    }
+    if text_start < content.len() {
+        parts.push(ClozePart::Text(&content[text_start..]));
+    }
+    parts
+}
+
+/// The distinct cloze indices of `content` in ascending order, empty for a card without deletions.
+pub fn indices(content: &str) -> Vec<u32> {
+    let mut indices: Vec<_> = parse(content).into_iter()
+        .filter_map(|part| match part {
+            ClozePart::Deletion { index, .. } => Some(index),
+            ClozePart::Text(_) => None,
+        })
+        .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    indices
+}
+
+/// The answers of the deletions with `index`, joined by a space.
+pub fn answer(content: &str, index: u32) -> String {
+    let answers: Vec<_> = parse(content).into_iter()
+        .filter_map(|part| match part {
+            ClozePart::Deletion { index: found, answer, .. } if found == index => Some(answer),
+            _ => None,
+        })
+        .collect();
+    answers.join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{answer, indices, parse, ClozePart};
+
+    #[test]
+    fn test_parse_cloze() {
+        let content = "{{c1::Tokyo::city}} is the capital of {{c2::Japan}}, {{c1::東京}} {{c}} {{c3:x}}";
+        let parts = parse(content);
+        assert_eq!(parts[0], ClozePart::Deletion { index: 1, answer: "Tokyo", hint: Some("city") });
+        assert_eq!(parts[1], ClozePart::Text(" is the capital of "));
+        assert_eq!(parts[2], ClozePart::Deletion { index: 2, answer: "Japan", hint: None });
+        assert_eq!(parts.last(), Some(&ClozePart::Text(" {{c}} {{c3:x}}")));
+        assert_eq!(indices(content), vec![1, 2]);
+        assert_eq!(answer(content, 1), "Tokyo 東京");
+        assert!(indices("no deletion").is_empty());
+    }
+
+    #[test]
+    fn test_cloze_cards_are_opt_in() {
+        use crate::repository::deck::{CardDirection, CardItem, CardVariant};
+        let entries: Vec<CardItem> = serde_yaml::from_str(
+            "- name: template syntax\n  content: '{{c1::x}} stays as written'\n- name: capital\n  content: '{{c1::Tokyo}} is in {{c2::Japan}}'\n  type: cloze\n",
+        ).unwrap();
+        let variants = |entry: &CardItem| -> Vec<_> {
+            entry.variant_cards(CardDirection::Forward).iter().map(|card| card.variant).collect()
+        };
+        assert_eq!(variants(&entries[0]), vec![CardVariant::Forward]);
+        assert_eq!(variants(&entries[1]), vec![CardVariant::Cloze(1), CardVariant::Cloze(2)]);
+    }
+}
@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// Top-level `collection.yaml` listing the decks studied together.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectionConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Deck directories relative to the collection file.
+    pub decks: Vec<String>,
+}
@@ -0,0 +1,39 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// Cards studied on one day, persisted so that daily limits survive a restart.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DailyCounter {
+    pub date: NaiveDate,
+    pub new: usize,
+    pub reviews: usize,
+}
+
+impl DailyCounter {
+    pub fn new(date: NaiveDate) -> Self {
+        DailyCounter { date, new: 0, reviews: 0 }
+    }
+    /// The counter for `today`, starting from zero when the stored one is from another day.
+    pub fn for_day(self, today: NaiveDate) -> Self {
+        if self.date == today {
+            self
+        } else {
+            DailyCounter::new(today)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+    use super::DailyCounter;
+
+    #[test]
+    fn test_for_day() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let counter = DailyCounter { date: day, new: 3, reviews: 10 };
+        assert_eq!(counter.for_day(day), counter);
+        let next_day = day.succ_opt().unwrap();
+        assert_eq!(counter.for_day(next_day), DailyCounter::new(next_day));
+    }
+}
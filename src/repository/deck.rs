@@ -1,48 +1,338 @@
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Deserializer};
 use uuid::Uuid;
+use crate::repository::cloze;
 use crate::repository::lock::CardItemIdentify;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardItem {
+    /// Explicit identity that survives edits of `name` and `content`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub glance: Option<String>,
     pub content: String,
-    pub tags: Option<Vec<String>>
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Which way the card is asked, the deck's `direction` if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub direction: Option<CardDirection>,
+    /// `cloze` makes a card of each deletion number in the content, like `{{c1::…}}`, instead of asking the content.
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub card_type: Option<CardType>,
+    /// Which of the cards generated from this entry it is.
+    #[serde(skip)]
+    pub variant: CardVariant,
+}
+
+/// The schedulable cards of one entry in a card file, generated when the cards are loaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CardVariant {
+    /// Show the name, answer with the content.
+    #[default]
+    Forward,
+    /// Show the content, answer with the name.
+    Reverse,
+    /// Show the content with the deletions of this index blanked.
+    Cloze(u32),
+}
+
+impl CardVariant {
+    /// How the card is marked in listings and keyed in its identity, none for forward cards.
+    pub fn label(&self) -> Option<String> {
+        match self {
+            CardVariant::Forward => None,
+            CardVariant::Reverse => Some("reverse".to_string()),
+            CardVariant::Cloze(index) => Some(format!("c{}", index)),
+        }
+    }
 }
 
 impl CardItem {
     pub fn get_id(&self) -> CardItemIdentify {
+        match self.id {
+            Some(ref id) => {
+                let hashed_id = Uuid::new_v5(&Uuid::NAMESPACE_URL, id.as_bytes());
+                self.directed(CardItemIdentify(hashed_id, hashed_id))
+            }
+            None => self.content_id(),
+        }
+    }
+    /// Generated cards get identities of their own, derived from the ones of the forward card.
+    fn variant_uuid(&self, uuid: Uuid) -> Uuid {
+        match self.variant.label() {
+            None => uuid,
+            Some(key) => Uuid::new_v5(&uuid, key.as_bytes()),
+        }
+    }
+    fn directed(&self, id: CardItemIdentify) -> CardItemIdentify {
+        CardItemIdentify(self.variant_uuid(id.0), self.variant_uuid(id.1))
+    }
+    fn with_variant(&self, variant: CardVariant) -> CardItem {
+        CardItem { variant, ..self.clone() }
+    }
+    pub fn is_cloze(&self) -> bool {
+        self.card_type == Some(CardType::Cloze)
+    }
+    /// The cards to schedule for this entry: one per cloze index for cloze entries,
+    /// otherwise one per direction with the forward one first.
+    /// A cloze entry without deletions is asked like other entries.
+    pub fn variant_cards(&self, default: CardDirection) -> Vec<CardItem> {
+        let cloze_indices = if self.is_cloze() { cloze::indices(&self.content) } else { Vec::new() };
+        if !cloze_indices.is_empty() {
+            return cloze_indices.into_iter().map(|index| self.with_variant(CardVariant::Cloze(index))).collect();
+        }
+        match self.direction.unwrap_or(default) {
+            CardDirection::Forward => vec![self.with_variant(CardVariant::Forward)],
+            CardDirection::Reverse => vec![self.with_variant(CardVariant::Reverse)],
+            CardDirection::Both => vec![self.with_variant(CardVariant::Forward), self.with_variant(CardVariant::Reverse)],
+        }
+    }
+    /// The entry of the card file this card was generated from.
+    pub fn entry(&self) -> CardItem {
+        self.with_variant(CardVariant::Forward)
+    }
+    /// What the card asks for: the content, the name of a reverse card or the deletions of a cloze card.
+    pub fn answer(&self) -> String {
+        match self.variant {
+            CardVariant::Forward => self.content.clone(),
+            CardVariant::Reverse => self.name.clone(),
+            CardVariant::Cloze(index) => cloze::answer(&self.content, index),
+        }
+    }
+    /// The identity derived from name and content, used by cards without an explicit `id`.
+    pub fn content_id(&self) -> CardItemIdentify {
         let hashed_name = Uuid::new_v5(&Uuid::NAMESPACE_OID, self.name.as_bytes());
         let hashed_content = Uuid::new_v5(&hashed_name, self.content.as_bytes());
-        CardItemIdentify(hashed_name, hashed_content)
+        self.directed(CardItemIdentify(hashed_name, hashed_content))
+    }
+    /// Hash of the content alone, so that a renamed card can still be recognized.
+    pub fn content_hash(&self) -> Uuid {
+        self.variant_uuid(Uuid::new_v5(&Uuid::NAMESPACE_OID, self.content.as_bytes()))
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Deck {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Card files, glob patterns like `chapters/**/*.yml` or directories, relative to the deck.
     pub card_files: Vec<String>,
+    /// Glob patterns of files left out when expanding `card_files`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_files: Vec<String>,
     #[serde(default = "default_algorithm")]
     pub algorithm: RepeatAlgorithm,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub fsrs_option: Option<FsrsOption>,
+    /// How many never-seen cards may be introduced per day.
+    #[serde(default = "default_new_per_day")]
+    pub new_per_day: usize,
+    /// How many reviews of already seen cards may be done per day.
+    #[serde(default = "default_reviews_per_day")]
+    pub reviews_per_day: usize,
+    #[serde(default)]
+    pub new_card_order: NewCardOrder,
+    /// Type answers before they are revealed, compared with these normalizations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typed_answer: Option<TypedAnswerOption>,
+    /// Which way cards are asked unless they set their own `direction`.
+    #[serde(default, skip_serializing_if = "CardDirection::is_forward")]
+    pub direction: CardDirection,
+    /// Local hour at which a new study day begins.
+    #[serde(default = "default_day_rollover_hour", deserialize_with = "deserialize_day_rollover_hour")]
+    pub day_rollover_hour: u32,
+    /// Steps like `1m` or `10m` a new card is repeated at before its first long-term interval.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub learning_steps: Vec<LearningStep>,
+    /// Steps a forgotten card is repeated at before it gets a long-term interval again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relearning_steps: Vec<LearningStep>,
+    /// Settings taken from parent decks, left out again when the deck is written.
+    #[serde(skip)]
+    pub inherited: serde_json::Map<String, serde_json::Value>,
+}
+
+fn default_day_rollover_hour() -> u32 {
+    4
+}
+
+fn deserialize_day_rollover_hour<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let hour = u32::deserialize(deserializer)?;
+    if hour > 23 {
+        return Err(serde::de::Error::custom(format!("day_rollover_hour {hour} is not an hour of the day, it must be between 0 and 23")));
+    }
+    Ok(hour)
+}
+
+impl Deck {
+    pub fn new(name: String, card_files: Vec<String>) -> Self {
+        Deck {
+            name,
+            description: None,
+            card_files,
+            exclude_files: Vec::new(),
+            algorithm: default_algorithm(),
+            fsrs_option: None,
+            new_per_day: default_new_per_day(),
+            reviews_per_day: default_reviews_per_day(),
+            new_card_order: NewCardOrder::default(),
+            typed_answer: None,
+            direction: CardDirection::default(),
+            day_rollover_hour: default_day_rollover_hour(),
+            learning_steps: Vec::new(),
+            relearning_steps: Vec::new(),
+            inherited: serde_json::Map::new(),
+        }
+    }
+}
+
+fn default_new_per_day() -> usize {
+    20
+}
+
+fn default_reviews_per_day() -> usize {
+    200
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NewCardOrder {
+    /// In the order of `card_files` and of the cards inside each file.
+    #[default]
+    Sequential,
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CardDirection {
+    /// Show the name, answer with the content.
+    #[default]
+    Forward,
+    /// Show the content, answer with the name.
+    Reverse,
+    /// Both ways, as two cards that are not studied on the same day.
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CardType {
+    /// Ask the name, answer with the content.
+    #[default]
+    Basic,
+    /// Ask the content with one deletion number blanked at a time.
+    Cloze,
+}
+
+impl CardDirection {
+    fn is_forward(&self) -> bool {
+        *self == CardDirection::Forward
+    }
+}
+
+impl RepeatAlgorithm {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RepeatAlgorithm::Fsrs => "fsrs",
+            RepeatAlgorithm::Sm2 => "sm2",
+            RepeatAlgorithm::Leitner => "leitner",
+        }
+    }
 }
 
 fn default_algorithm() -> RepeatAlgorithm {
     RepeatAlgorithm::Fsrs
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, clap::ValueEnum)]
 #[serde(rename_all = "lowercase")]
 pub enum RepeatAlgorithm {
     #[default]
-    Fsrs
+    Fsrs,
+    /// SuperMemo 2 with ease factors, as in Anki before FSRS.
+    Sm2,
+    /// Leitner boxes with doubling intervals.
+    Leitner,
+}
+
+/// A delay written with a unit, like `30s`, `10m`, `1h` or `2d`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct LearningStep {
+    seconds: u32,
+}
+
+impl LearningStep {
+    pub fn days(&self) -> f32 {
+        self.seconds as f32 / (24.0 * 60.0 * 60.0)
+    }
+}
+
+const STEP_UNITS: [(char, u32); 4] = [('d', 24 * 60 * 60), ('h', 60 * 60), ('m', 60), ('s', 1)];
+
+impl TryFrom<String> for LearningStep {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("invalid learning step `{}`, expected a number with s, m, h or d like `10m`", text);
+        let unit = text.chars().last().ok_or_else(invalid)?;
+        let (_, unit_seconds) = STEP_UNITS.iter().find(|(name, _)| *name == unit).ok_or_else(invalid)?;
+        let count: u32 = text[..text.len() - 1].trim().parse().map_err(|_| invalid())?;
+        match count.checked_mul(*unit_seconds) {
+            Some(seconds) if seconds > 0 => Ok(LearningStep { seconds }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<LearningStep> for String {
+    fn from(step: LearningStep) -> Self {
+        let (unit, unit_seconds) = STEP_UNITS.iter()
+            .find(|(_, unit_seconds)| step.seconds.is_multiple_of(*unit_seconds))
+            .unwrap();
+        format!("{}{}", step.seconds / unit_seconds, unit)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FsrsOption {
     #[serde(default = "default_retention")]
     pub retention: f32,
+    /// Personalized FSRS weights, written by `mem-yaml optimize`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weights: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TypedAnswerOption {
+    #[serde(default = "default_true")]
+    pub ignore_case: bool,
+    /// Ignore leading and trailing whitespace and treat runs of whitespace as one space.
+    #[serde(default = "default_true")]
+    pub collapse_whitespace: bool,
+    #[serde(default)]
+    pub ignore_punctuation: bool,
+    /// Treat full-width and half-width forms of letters, digits and katakana alike.
+    #[serde(default = "default_true")]
+    pub normalize_width: bool,
+}
+
+impl Default for TypedAnswerOption {
+    fn default() -> Self {
+        TypedAnswerOption {
+            ignore_case: true,
+            collapse_whitespace: true,
+            ignore_punctuation: false,
+            normalize_width: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
 }
 
 pub fn default_retention() -> f32 {
@@ -0,0 +1,300 @@
+use std::path::Path;
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::repository::deck::CardItem;
+
+/// Format of a deck metadata or card file, detected from its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+/// A parse failure with the 1-based line and column the parser reported, if any.
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    pub line_column: Option<(usize, usize)>,
+}
+
+/// A field missing from the schema, with the index of the card it was found in.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownField {
+    pub card: Option<usize>,
+    pub key: String,
+}
+
+impl UnknownField {
+    fn from_path(path: &serde_ignored::Path) -> UnknownField {
+        let mut card = None;
+        let mut key = String::new();
+        let mut current = Some(path);
+        while let Some(path) = current {
+            current = match path {
+                serde_ignored::Path::Root => None,
+                serde_ignored::Path::Seq { parent, index } => {
+                    card = card.or(Some(*index));
+                    Some(*parent)
+                }
+                serde_ignored::Path::Map { parent, key: map_key } => {
+                    if key.is_empty() {
+                        key = map_key.clone();
+                    }
+                    Some(*parent)
+                }
+                serde_ignored::Path::Some { parent }
+                | serde_ignored::Path::NewtypeStruct { parent }
+                | serde_ignored::Path::NewtypeVariant { parent } => Some(*parent),
+            };
+        }
+        UnknownField { card, key }
+    }
+}
+
+/// 1-based line and column of a byte offset in `text`.
+pub fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+    (line, column)
+}
+
+/// Drop the position serde_yaml and serde_json append to their messages, it is reported separately.
+fn without_location(message: String) -> String {
+    match message.rfind(" at line ") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// Replace the lines of the top-level YAML mapping `key` with `block`, or append it, keeping the other lines as written.
+fn set_yaml_key(content: &str, key: &str, block: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let is_key_line = |line: &str| {
+        [format!("{}:", key), format!("\"{}\":", key), format!("'{}':", key)].iter().any(|prefix| line.starts_with(prefix.as_str()))
+    };
+    let Some(start) = lines.iter().position(|line| is_key_line(line)) else {
+        let separator = if content.is_empty() || content.ends_with('\n') { "" } else { "\n" };
+        return format!("{}{}{}", content, separator, block);
+    };
+    // the value goes on over indented lines and block sequence items, blank lines between them included
+    let mut end = start + 1;
+    for (index, line) in lines.iter().enumerate().skip(start + 1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !(line.starts_with([' ', '\t']) || line.starts_with('-') && !line.starts_with("---")) {
+            break;
+        }
+        end = index + 1;
+    }
+    let mut edited: String = lines[..start].concat();
+    edited.push_str(block);
+    edited.push_str(&lines[end..].concat());
+    edited
+}
+
+/// TOML documents must be tables, so card files keep their cards in `[[cards]]`.
+#[derive(Serialize, Deserialize)]
+struct TomlCards {
+    cards: Vec<CardItem>,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &Path) -> Result<FileFormat> {
+        let extension = path.extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("yaml") | Some("yml") => Ok(FileFormat::Yaml),
+            Some("json") => Ok(FileFormat::Json),
+            Some("toml") => Ok(FileFormat::Toml),
+            _ => bail!("Unsupported file format: {}", path.display()),
+        }
+    }
+    pub fn parse<T: DeserializeOwned>(&self, content: &str) -> Result<T> {
+        Ok(match self {
+            FileFormat::Yaml => serde_yaml::from_str(content)?,
+            FileFormat::Json => serde_json::from_str(content)?,
+            FileFormat::Toml => toml::from_str(content)?,
+        })
+    }
+    /// Parse like [`FileFormat::parse`], also collecting fields the schema ignores.
+    pub fn parse_checked<T: DeserializeOwned>(&self, content: &str) -> Result<(T, Vec<UnknownField>), ParseError> {
+        let mut unknown = Vec::new();
+        let track = |path: serde_ignored::Path| unknown.push(UnknownField::from_path(&path));
+        let parsed = match self {
+            FileFormat::Yaml => {
+                serde_ignored::deserialize(serde_yaml::Deserializer::from_str(content), track).map_err(|error| ParseError {
+                    line_column: error.location().map(|location| (location.line(), location.column())),
+                    message: without_location(error.to_string()),
+                })
+            }
+            FileFormat::Json => {
+                let mut deserializer = serde_json::Deserializer::from_str(content);
+                serde_ignored::deserialize(&mut deserializer, track)
+                    .and_then(|parsed| deserializer.end().map(|_| parsed))
+                    .map_err(|error| ParseError {
+                        line_column: (error.line() > 0).then(|| (error.line(), error.column())),
+                        message: without_location(error.to_string()),
+                    })
+            }
+            FileFormat::Toml => {
+                serde_ignored::deserialize(toml::Deserializer::new(content), track).map_err(|error| ParseError {
+                    line_column: error.span().map(|span| line_column(content, span.start)),
+                    message: error.message().to_string(),
+                })
+            }
+        }?;
+        Ok((parsed, unknown))
+    }
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<String> {
+        Ok(match self {
+            FileFormat::Yaml => serde_yaml::to_string(value)?,
+            FileFormat::Json => serde_json::to_string_pretty(value)?,
+            FileFormat::Toml => toml::to_string_pretty(value)?,
+        })
+    }
+    /// Set the top-level `key` of the document in `content` to `value`, changing as little of the text as possible
+    /// so that comments and the order of the other settings are kept.
+    pub fn set_key(&self, content: &str, key: &str, value: &serde_json::Value) -> Result<String> {
+        let mut setting = serde_json::Map::new();
+        setting.insert(key.to_string(), value.clone());
+        Ok(match self {
+            FileFormat::Yaml => {
+                let edited = set_yaml_key(content, key, &serde_yaml::to_string(&setting)?);
+                // documents the line-based edit cannot handle, like flow mappings, are written again instead
+                let parsed: serde_json::Value = self.parse(&edited).unwrap_or_default();
+                if parsed.get(key) == Some(value) {
+                    edited
+                } else {
+                    let mut document: serde_json::Value = self.parse(content)?;
+                    let Some(fields) = document.as_object_mut() else { bail!("The document is not a mapping") };
+                    fields.insert(key.to_string(), value.clone());
+                    self.serialize(&document)?
+                }
+            }
+            FileFormat::Json => {
+                let mut document: serde_json::Value = self.parse(content)?;
+                let Some(fields) = document.as_object_mut() else { bail!("The document is not an object") };
+                fields.insert(key.to_string(), value.clone());
+                let mut edited = self.serialize(&document)?;
+                edited.push('\n');
+                edited
+            }
+            FileFormat::Toml => {
+                let mut document: toml_edit::DocumentMut = content.parse()?;
+                let setting: toml_edit::DocumentMut = toml::to_string(&setting)?.parse()?;
+                let Some(item) = setting.get(key) else { bail!("{} cannot be written to TOML", key) };
+                match (document.get_mut(key), item.clone()) {
+                    // keep the comments and blank lines around a replaced value or table, and where the table was
+                    (Some(toml_edit::Item::Value(current)), toml_edit::Item::Value(mut replacement)) => {
+                        *replacement.decor_mut() = current.decor().clone();
+                        *current = replacement;
+                    }
+                    (Some(toml_edit::Item::Table(current)), toml_edit::Item::Table(mut replacement)) => {
+                        *replacement.decor_mut() = current.decor().clone();
+                        if let Some(position) = current.position() {
+                            replacement.set_position(position);
+                        }
+                        *current = replacement;
+                    }
+                    (_, item) => {
+                        document.insert(key, item);
+                    }
+                }
+                document.to_string()
+            }
+        })
+    }
+    pub fn parse_cards(&self, content: &str) -> Result<Vec<CardItem>> {
+        match self {
+            FileFormat::Toml => Ok(self.parse::<TomlCards>(content)?.cards),
+            _ => self.parse(content),
+        }
+    }
+    pub fn parse_cards_checked(&self, content: &str) -> Result<(Vec<CardItem>, Vec<UnknownField>), ParseError> {
+        match self {
+            FileFormat::Toml => self.parse_checked::<TomlCards>(content).map(|(toml_cards, unknown)| (toml_cards.cards, unknown)),
+            _ => self.parse_checked(content),
+        }
+    }
+    /// Add `cards` after the cards of a card file, appending them as text where the format allows it
+    /// so that the existing entries keep their comments and layout.
+    pub fn append_cards(&self, content: &str, cards: &[CardItem]) -> Result<String> {
+        let existing = if content.trim().is_empty() { Vec::new() } else { self.parse_cards(content)? };
+        if *self != FileFormat::Json && !content.trim().is_empty() {
+            let separator = if content.ends_with('\n') { "" } else { "\n" };
+            let appended = format!("{}{}{}", content, separator, self.serialize_cards(cards)?);
+            // flow sequences and the like cannot be continued as text and are written again instead
+            if self.parse_cards(&appended).is_ok_and(|parsed| parsed.len() == existing.len() + cards.len()) {
+                return Ok(appended);
+            }
+        }
+        self.serialize_cards(&[existing, cards.to_vec()].concat())
+    }
+    pub fn serialize_cards(&self, cards: &[CardItem]) -> Result<String> {
+        match self {
+            FileFormat::Toml => self.serialize(&TomlCards { cards: cards.to_vec() }),
+            _ => self.serialize(&cards),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use crate::repository::deck::{CardItem, CardVariant};
+    use super::FileFormat;
+
+    #[test]
+    fn test_set_key_keeps_the_rest_of_the_document() {
+        let yaml = "# my deck\nname: Spanish\nfsrs_option:\n  retention: 0.9 # rather high\n\n# daily limits\nnew_per_day: 5\n";
+        let edited = FileFormat::Yaml.set_key(yaml, "fsrs_option", &json!({ "retention": 0.8, "weights": [0.5] })).unwrap();
+        assert_eq!(edited, "# my deck\nname: Spanish\nfsrs_option:\n  retention: 0.8\n  weights:\n  - 0.5\n\n# daily limits\nnew_per_day: 5\n");
+        let edited = FileFormat::Yaml.set_key("name: Spanish\ncard_files:\n- a.yml\n", "algorithm", &json!("sm2")).unwrap();
+        assert_eq!(edited, "name: Spanish\ncard_files:\n- a.yml\nalgorithm: sm2\n");
+        let edited = FileFormat::Yaml.set_key("{name: Spanish}", "algorithm", &json!("sm2")).unwrap();
+        assert_eq!(FileFormat::Yaml.parse::<serde_json::Value>(&edited).unwrap(), json!({ "name": "Spanish", "algorithm": "sm2" }));
+
+        let toml = "# my deck\nname = \"Spanish\"\nalgorithm = \"fsrs\" # for now\nnew_per_day = 5\n";
+        let edited = FileFormat::Toml.set_key(toml, "algorithm", &json!("leitner")).unwrap();
+        assert_eq!(edited, "# my deck\nname = \"Spanish\"\nalgorithm = \"leitner\" # for now\nnew_per_day = 5\n");
+        let edited = FileFormat::Toml.set_key(&edited, "fsrs_option", &json!({ "retention": 0.8 })).unwrap();
+        assert!(edited.starts_with("# my deck\nname = \"Spanish\"\nalgorithm = \"leitner\" # for now\nnew_per_day = 5\n"));
+        assert_eq!(FileFormat::Toml.parse::<serde_json::Value>(&edited).unwrap()["fsrs_option"], json!({ "retention": 0.8 }));
+        let tables = "name = \"Spanish\"\n\n[fsrs_option]\nretention = 0.9\n\n[typed_answer]\nignore_case = true\n";
+        let edited = FileFormat::Toml.set_key(tables, "fsrs_option", &json!({ "retention": 0.8 })).unwrap();
+        assert_eq!(edited, "name = \"Spanish\"\n\n[fsrs_option]\nretention = 0.8\n\n[typed_answer]\nignore_case = true\n");
+
+        let edited = FileFormat::Json.set_key("{\"name\": \"Spanish\", \"new_per_day\": 5}", "algorithm", &json!("sm2")).unwrap();
+        assert_eq!(edited, "{\n  \"name\": \"Spanish\",\n  \"new_per_day\": 5,\n  \"algorithm\": \"sm2\"\n}\n");
+    }
+
+    #[test]
+    fn test_append_cards() {
+        let cards = [CardItem {
+            id: None,
+            name: "perro".to_string(),
+            glance: None,
+            content: "dog".to_string(),
+            tags: None,
+            direction: None,
+            card_type: None,
+            variant: CardVariant::Forward,
+        }];
+        let yaml = "# nouns\n- name: gato\n  content: cat";
+        let appended = FileFormat::Yaml.append_cards(yaml, &cards).unwrap();
+        assert_eq!(appended, "# nouns\n- name: gato\n  content: cat\n- name: perro\n  content: dog\n");
+        // a flow sequence cannot be continued and is written again
+        let appended = FileFormat::Yaml.append_cards("[{name: gato, content: cat}]", &cards).unwrap();
+        assert_eq!(FileFormat::Yaml.parse_cards(&appended).unwrap().len(), 2);
+        let toml = "# nouns\n[[cards]]\nname = \"gato\"\ncontent = \"cat\"\n";
+        let appended = FileFormat::Toml.append_cards(toml, &cards).unwrap();
+        assert!(appended.starts_with(toml));
+        assert_eq!(FileFormat::Toml.parse_cards(&appended).unwrap().len(), 2);
+        assert_eq!(FileFormat::Json.parse_cards(&FileFormat::Json.append_cards("", &cards).unwrap()).unwrap().len(), 1);
+    }
+}
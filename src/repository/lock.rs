@@ -1,29 +1,83 @@
 use std::collections::{HashMap, HashSet};
-use chrono::NaiveDateTime;
+use std::fmt::Display;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use crate::repository::deck::CardItem;
+use crate::repository::deck::{CardItem, CardVariant};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct CardItemIdentify(pub Uuid, pub Uuid);
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct MemoryState {
-    pub last_reviewed: NaiveDateTime,
-    pub interval: f32,
-    pub stability: f32,
-    pub difficulty: f32,
+impl Display for CardItemIdentify {
+    /// The content half is derived from both name and content, so it is enough to tell cards apart.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.1)
+    }
 }
 
-impl Into<fsrs::MemoryState> for &MemoryState {
-    fn into(self) -> fsrs::MemoryState {
-        fsrs::MemoryState {
-            stability: self.stability,
-            difficulty: self.difficulty,
+/// Timestamps are stored in UTC. Older lock files wrote them without an offset, also in UTC.
+pub(crate) mod utc_datetime {
+    use super::*;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        time.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        if let Ok(time) = DateTime::parse_from_rfc3339(&text) {
+            return Ok(time.with_timezone(&Utc));
         }
+        text.parse::<NaiveDateTime>()
+            .map(|time| time.and_utc())
+            .map_err(serde::de::Error::custom)
     }
 }
 
+/// The part of a memory state kept by the scheduling algorithm, told apart by its fields.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum SchedulerMemory {
+    Fsrs {
+        stability: f32,
+        difficulty: f32,
+    },
+    Sm2 {
+        ease: f32,
+        /// Successful reviews in a row.
+        repetitions: u32,
+    },
+    Leitner {
+        /// Zero-based index of the box the card is in.
+        #[serde(rename = "box")]
+        leitner_box: u32,
+    },
+}
+
+/// The learning or relearning step a card is at, counted from zero.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LearningPhase {
+    /// A new card repeated within the day before its first long-term interval.
+    Learning(u32),
+    /// A forgotten card repeated within the day before a long-term interval again.
+    Relearning(u32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MemoryState {
+    #[serde(with = "utc_datetime")]
+    pub last_reviewed: DateTime<Utc>,
+    /// Days until the next review.
+    pub interval: f32,
+    #[serde(flatten)]
+    pub memory: SchedulerMemory,
+    /// Set while the card goes through learning steps, the interval is then the one of the step.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<LearningPhase>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct LockItem {
     pub name: Uuid,
@@ -31,6 +85,8 @@ pub struct LockItem {
     #[serde(flatten)]
     pub state: Option<MemoryState>,
     pub ignored: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<Uuid>,
 }
 
 impl LockItem {
@@ -41,6 +97,7 @@ impl LockItem {
             content: hashed_content,
             state: None,
             ignored: false,
+            content_hash: Some(card.content_hash()),
         }
     }
     pub fn get_id(&self) -> CardItemIdentify {
@@ -48,43 +105,99 @@ impl LockItem {
     }
 }
 
-pub fn update_lock_item_list(old_list: Vec<LockItem>, new_content: &Vec<CardItem>) -> Vec<LockItem> {
-    // Get the ids of the old list and the new content
-    let old_ids: HashSet<_> = old_list.iter().map(|item| item.get_id()).collect();
-    let new_ids: HashSet<_> = new_content.iter().map(|item| item.get_id()).collect();
-
-    // Get the ids that are new and removed
-    let new_item_ids: Vec<_> = new_ids.difference(&old_ids).collect();
-    let removed_item_ids: Vec<_> = old_ids.difference(&new_ids).collect();
-
-    // use hash map to get fast access to the items
-    let new_content_map: HashMap<CardItemIdentify, &CardItem> = new_content
-        .iter().map(|item| (item.get_id(), item)).collect();
-    let old_list_map: HashMap<CardItemIdentify, &LockItem> = old_list
-        .iter().map(|item| (item.get_id(), item)).collect();
-
-    let new_content: Vec<_> = new_item_ids.iter().map(|id| {
-        LockItem::new_from_card(new_content_map[id])
-    }).collect();
-    let old_list_filtered: Vec<_> = old_list_map.into_iter().filter_map(|(id, item)| {
-        if removed_item_ids.contains(&&id) {
-            None
-        } else {
-            Some(item.to_owned())
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationKind {
+    /// The card got an explicit `id`.
+    IdAssigned,
+    /// The name is unchanged but the content was edited.
+    ContentChanged,
+    /// The content is unchanged but the name was edited.
+    Renamed,
+}
+
+/// A card whose memory state was carried over from a lock item with another identity.
+#[derive(Debug, Clone)]
+pub struct LockMigration {
+    pub card_name: String,
+    pub from: CardItemIdentify,
+    pub to: CardItemIdentify,
+    pub kind: MigrationKind,
+}
+
+fn unique_match<'a>(mut candidates: impl Iterator<Item = &'a LockItem>) -> Option<CardItemIdentify> {
+    let first = candidates.next()?;
+    match candidates.next() {
+        None => Some(first.get_id()),
+        Some(_) => None,
+    }
+}
+
+fn find_migration(unmatched: &HashMap<CardItemIdentify, LockItem>, card: &CardItem) -> Option<(CardItemIdentify, MigrationKind)> {
+    let content_id = card.content_id();
+    if card.id.is_some() && unmatched.contains_key(&content_id) {
+        return Some((content_id, MigrationKind::IdAssigned));
+    }
+    if let Some(id) = unique_match(unmatched.values().filter(|item| item.name == content_id.0)) {
+        return Some((id, MigrationKind::ContentChanged));
+    }
+    let content_hash = card.content_hash();
+    unique_match(unmatched.values().filter(|item| match item.content_hash {
+        Some(hash) => hash == content_hash,
+        // lock files written before content hashes only hold forward cards without an explicit id,
+        // whose content half is derived from the name half and the content
+        None => card.variant == CardVariant::Forward && Uuid::new_v5(&item.name, card.content.as_bytes()) == item.content,
+    }))
+        .map(|id| (id, MigrationKind::Renamed))
+}
+
+pub fn update_lock_item_list(old_list: Vec<LockItem>, new_content: &[CardItem]) -> (Vec<LockItem>, Vec<LockMigration>) {
+    // use hash map to get fast access to the items, matched items are taken out of it
+    let mut old_list_map: HashMap<CardItemIdentify, LockItem> = old_list
+        .into_iter().map(|item| (item.get_id(), item)).collect();
+    let mut seen_ids = HashSet::new();
+    let mut new_list = Vec::new();
+    let mut unmatched_cards = Vec::new();
+    for card in new_content {
+        let id = card.get_id();
+        if !seen_ids.insert(id) {
+            continue;
+        }
+        match old_list_map.remove(&id) {
+            Some(mut item) => {
+                item.content_hash = Some(card.content_hash());
+                new_list.push(item);
+            }
+            None => unmatched_cards.push(card),
+        }
+    }
+
+    // Cards without a lock item may be edited versions of cards that disappeared
+    let mut migrations = Vec::new();
+    for card in unmatched_cards {
+        let mut item = LockItem::new_from_card(card);
+        if let Some((old_id, kind)) = find_migration(&old_list_map, card) {
+            let old_item = old_list_map.remove(&old_id).unwrap();
+            item.state = old_item.state;
+            item.ignored = old_item.ignored;
+            migrations.push(LockMigration {
+                card_name: card.name.clone(),
+                from: old_id,
+                to: item.get_id(),
+                kind,
+            });
         }
-    }).collect();
-    let mut new_list = old_list_filtered;
-    new_list.extend(new_content);
-    new_list.sort_by(|a, b| a.get_id().0.cmp(&b.get_id().0));
-    new_list
+        new_list.push(item);
+    }
+    new_list.sort_by_key(|item| item.get_id().0);
+    (new_list, migrations)
 }
 
 
 #[cfg(test)]
 mod test {
     use uuid::Uuid;
-    use crate::repository::deck::CardItem;
-    use crate::repository::lock::{LockItem, MemoryState};
+    use crate::repository::deck::{CardItem, CardVariant};
+    use crate::repository::lock::{LockItem, MemoryState, MigrationKind, SchedulerMemory};
 
     #[test]
     fn test_update_lock_item_list() {
@@ -107,36 +220,47 @@ mod test {
                 content: content2,
                 state: None,
                 ignored: false,
+                content_hash: None,
             },
             LockItem {
                 name: name1,
                 content: content1,
                 state: MemoryState {
-                    last_reviewed: chrono::NaiveDateTime::UNIX_EPOCH,
+                    last_reviewed: chrono::DateTime::UNIX_EPOCH,
                     interval: 0.3,
-                    stability: 0.4,
-                    difficulty: 0.5,
+                    memory: SchedulerMemory::Fsrs { stability: 0.4, difficulty: 0.5 },
+                    step: None,
                 }.into(),
                 ignored: false,
+                content_hash: None,
             },
         ];
         let new_content = vec![
             CardItem {
+                id: None,
                 name: "name_1".to_string(),
                 glance: None,
                 content: "content_1".to_string(),
                 tags: None,
+                direction: None,
+                card_type: None,
+                variant: CardVariant::Forward,
             },
             CardItem {
+                id: None,
                 name: "name_3".to_string(),
                 glance: None,
                 content: "content_3".to_string(),
                 tags: None,
+                direction: None,
+                card_type: None,
+                variant: CardVariant::Forward,
             },
         ];
-        let new_list = super::update_lock_item_list(old_list, &new_content);
+        let (new_list, migrations) = super::update_lock_item_list(old_list, &new_content);
 
         assert_eq!(new_list.len(), 2);
+        assert!(migrations.is_empty());
 
         assert_eq!(new_list[0].name, name3);
         assert_eq!(new_list[0].content, content3);
@@ -147,9 +271,88 @@ mod test {
         assert!(new_list[1].state.is_some());
 
         let state = new_list[1].state.as_ref().unwrap();
-        assert_eq!(state.last_reviewed, chrono::NaiveDateTime::UNIX_EPOCH);
+        assert_eq!(state.last_reviewed, chrono::DateTime::UNIX_EPOCH);
         assert_eq!(state.interval, 0.3);
-        assert_eq!(state.stability, 0.4);
-        assert_eq!(state.difficulty, 0.5);
+        assert_eq!(state.memory, SchedulerMemory::Fsrs { stability: 0.4, difficulty: 0.5 });
+    }
+
+    #[test]
+    fn test_update_lock_item_list_migrations() {
+        let card = |id: Option<&str>, name: &str, content: &str| CardItem {
+            id: id.map(str::to_string),
+            name: name.to_string(),
+            glance: None,
+            content: content.to_string(),
+            tags: None,
+            direction: None,
+            card_type: None,
+            variant: CardVariant::Forward,
+        };
+        let reviewed = |card: &CardItem, stability: f32| LockItem {
+            state: MemoryState {
+                last_reviewed: chrono::DateTime::UNIX_EPOCH,
+                interval: 1.0,
+                memory: SchedulerMemory::Fsrs { stability, difficulty: 5.0 },
+                step: None,
+            }.into(),
+            ..LockItem::new_from_card(card)
+        };
+        let old_list = vec![
+            reviewed(&card(None, "name_1", "content_1"), 1.0),
+            reviewed(&card(None, "name_2", "content_2"), 2.0),
+            reviewed(&card(None, "name_3", "content_3"), 3.0),
+            // written before lock items had a content hash
+            LockItem { content_hash: None, ..reviewed(&card(None, "name_4", "content_4"), 4.0) },
+        ];
+        let new_content = vec![
+            card(None, "name_1", "content_1 fixed"),
+            card(None, "name_2 fixed", "content_2"),
+            card(Some("card-3"), "name_3", "content_3"),
+            card(None, "name_4 fixed", "content_4"),
+        ];
+        let (new_list, migrations) = super::update_lock_item_list(old_list, &new_content);
+
+        assert_eq!(new_list.len(), 4);
+        let kinds: Vec<_> = migrations.iter().map(|migration| migration.kind).collect();
+        assert_eq!(kinds, vec![MigrationKind::ContentChanged, MigrationKind::Renamed, MigrationKind::IdAssigned, MigrationKind::Renamed]);
+        for (card, stability) in new_content.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            let item = new_list.iter().find(|item| item.get_id() == card.get_id()).unwrap();
+            assert_eq!(item.state.as_ref().unwrap().memory, SchedulerMemory::Fsrs { stability, difficulty: 5.0 });
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reverse_cards_keep_their_own_state() {
+        use crate::repository::deck::CardDirection;
+        let card = |content: &str| CardItem {
+            id: None,
+            name: "猫".to_string(),
+            glance: None,
+            content: content.to_string(),
+            tags: None,
+            direction: Some(CardDirection::Both),
+            card_type: None,
+            variant: CardVariant::Forward,
+        };
+        let old_cards = card("cat").variant_cards(CardDirection::Forward);
+        assert_eq!(old_cards.len(), 2);
+        assert_ne!(old_cards[0].get_id(), old_cards[1].get_id());
+        let old_list = old_cards.iter().zip([1.0, 2.0]).map(|(card, stability)| LockItem {
+            state: Some(MemoryState {
+                last_reviewed: chrono::DateTime::UNIX_EPOCH,
+                interval: 1.0,
+                memory: SchedulerMemory::Fsrs { stability, difficulty: 5.0 },
+                step: None,
+            }),
+            ..LockItem::new_from_card(card)
+        }).collect();
+
+        let new_cards = card("a cat").variant_cards(CardDirection::Forward);
+        let (new_list, migrations) = super::update_lock_item_list(old_list, &new_cards);
+        assert_eq!(migrations.len(), 2);
+        for (card, stability) in new_cards.iter().zip([1.0, 2.0]) {
+            let item = new_list.iter().find(|item| item.get_id() == card.get_id()).unwrap();
+            assert_eq!(item.state.as_ref().unwrap().memory, SchedulerMemory::Fsrs { stability, difficulty: 5.0 });
+        }
+    }
+}
@@ -1,2 +1,7 @@
+pub mod cloze;
+pub mod collection;
+pub mod daily_counter;
 pub mod deck;
-pub mod lock;
\ No newline at end of file
+pub mod file_format;
+pub mod lock;
+pub mod review_log;
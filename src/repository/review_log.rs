@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::learning::Difficulty;
+use crate::repository::lock::{utc_datetime, CardItemIdentify, LearningPhase, LockItem, SchedulerMemory};
+
+/// One answer given during a review session. The log is append-only, one JSON object per line:
+/// undoing an answer appends a copy of it marked `undone`, and readers drop both, and a card
+/// getting a new identity appends a [`ReviewLogAlias`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReviewLogEntry {
+    pub name: Uuid,
+    pub content: Uuid,
+    #[serde(with = "utc_datetime")]
+    pub reviewed_at: DateTime<Utc>,
+    pub rating: Difficulty,
+    pub elapsed_days: u32,
+    /// The scheduler memory of the card before and after the answer.
+    pub previous: Option<SchedulerMemory>,
+    pub current: SchedulerMemory,
+    /// The learning or relearning step the card was answered at, unset for new cards and reviews.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step: Option<LearningPhase>,
+    /// Milliseconds between showing the card and answering it.
+    pub time_spent: u64,
+    /// Set on the copy of an answer appended when the answer is undone.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undone: bool,
+}
+
+impl ReviewLogEntry {
+    pub fn new(
+        previous: &LockItem,
+        current: &LockItem,
+        rating: Difficulty,
+        elapsed_days: u32,
+        time_spent: std::time::Duration,
+    ) -> Self {
+        let state = current.state.as_ref().expect("answered card must have a memory state");
+        ReviewLogEntry {
+            name: current.name,
+            content: current.content,
+            reviewed_at: state.last_reviewed,
+            rating,
+            elapsed_days,
+            previous: previous.state.as_ref().map(|state| state.memory),
+            current: state.memory,
+            step: previous.state.as_ref().and_then(|state| state.step),
+            time_spent: time_spent.as_millis() as u64,
+            undone: false,
+        }
+    }
+    pub fn get_id(&self) -> CardItemIdentify {
+        CardItemIdentify(self.name, self.content)
+    }
+    /// Whether the answer was a review of a card with a long-term interval, rather than the first
+    /// answer of a new card or an answer at one of its learning or relearning steps.
+    pub fn is_review(&self) -> bool {
+        self.previous.is_some() && self.step.is_none()
+    }
+}
+
+/// A card of the log that got a new identity, like a renamed card.
+/// Readers move the answers logged before it to the new identity.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct ReviewLogAlias {
+    pub from: CardItemIdentify,
+    pub to: CardItemIdentify,
+}
+
+/// A line of the review log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReviewLogLine {
+    Answer(ReviewLogEntry),
+    Alias(ReviewLogAlias),
+}
+
+impl ReviewLogLine {
+    pub fn parse(line: &str) -> serde_json::Result<ReviewLogLine> {
+        let value: serde_json::Value = serde_json::from_str(line)?;
+        if value.get("from").is_some() {
+            Ok(ReviewLogLine::Alias(serde_json::from_value(value)?))
+        } else {
+            Ok(ReviewLogLine::Answer(serde_json::from_value(value)?))
+        }
+    }
+}
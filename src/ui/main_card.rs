@@ -2,32 +2,65 @@ use ratatui::buffer::Buffer;
 use ratatui::layout::Rect;
 use ratatui::prelude::*;
 use ratatui::widgets::*;
-use crate::repository::deck::CardItem;
+use crate::repository::cloze::{self, ClozePart};
+use crate::repository::deck::{CardItem, CardVariant};
 
 pub struct MainCard {
     pub is_revealed: bool,
     pub content: CardItem
 }
 
+/// The content of a cloze card with the deletions of `index` blanked, or highlighted once revealed.
+fn cloze_text(content: &str, index: u32, is_revealed: bool) -> Text<'static> {
+    let mut lines = vec![Line::default()];
+    let mut push = |text: &str, style: Style| {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                lines.push(Line::default());
+            }
+            lines.last_mut().unwrap().push_span(Span::styled(part.to_string(), style));
+        }
+    };
+    for part in cloze::parse(content) {
+        match part {
+            ClozePart::Text(text) => push(text, Style::default()),
+            ClozePart::Deletion { index: found, answer, .. } if found != index => push(answer, Style::default()),
+            ClozePart::Deletion { answer, .. } if is_revealed => push(answer, Style::default().cyan().bold()),
+            ClozePart::Deletion { hint, .. } => push(&format!("[{}]", hint.unwrap_or("...")), Style::default().cyan()),
+        }
+    }
+    Text::from(lines)
+}
+
 impl Widget for MainCard {
     fn render(self, area: Rect, buf: &mut Buffer)
     where
         Self: Sized,
     {
-        let name_text = Paragraph::new(self.content.name)
+        // reverse cards ask for the name from the content
+        let (prompt, answer) = match self.content.variant {
+            CardVariant::Reverse => (self.content.content, self.content.name),
+            _ => (self.content.name, self.content.content),
+        };
+        let name_text = Paragraph::new(prompt)
             .left_aligned()
             .bold();
         let glance_text = match self.content.glance {
             None => Paragraph::new(" "),
             Some(glance) => Paragraph::new(format!("glance: {}", glance))
         };
-        let mut content_text = Paragraph::new(self.content.content)
-            .left_aligned()
-            .wrap(Wrap { trim: true })
-            .white();
-        if self.is_revealed {
-            content_text = content_text.on_white();
-        }
+        let content_text = if let CardVariant::Cloze(index) = self.content.variant {
+            Paragraph::new(cloze_text(&answer, index, self.is_revealed))
+                .left_aligned()
+                .wrap(Wrap { trim: true })
+        } else if self.is_revealed {
+            Paragraph::new(answer)
+                .left_aligned()
+                .wrap(Wrap { trim: true })
+                .white()
+        } else {
+            Paragraph::new("?").dark_gray()
+        };
         let tags_text = match self.content.tags {
             None => Paragraph::new(" "),
             Some(tags) => {
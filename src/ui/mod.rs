@@ -1 +1,4 @@
-pub mod main_card;
\ No newline at end of file
+pub mod main_card;
+pub mod progress_header;
+pub mod session;
+pub mod typed_answer;
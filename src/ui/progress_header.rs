@@ -0,0 +1,30 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::prelude::*;
+use ratatui::widgets::*;
+use crate::core::Progress;
+
+pub struct ProgressHeader {
+    pub deck_name: String,
+    pub progress: Progress
+}
+
+impl Widget for ProgressHeader {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let line = Line::from(vec![
+            Span::from(self.deck_name).bold(),
+            Span::from("  due: "),
+            Span::from(self.progress.due.to_string()).red(),
+            Span::from("  new: "),
+            Span::from(self.progress.new.to_string()).blue(),
+            Span::from("  done: "),
+            Span::from(self.progress.done.to_string()).green(),
+        ]);
+        Paragraph::new(line)
+            .block(Block::default().borders(Borders::BOTTOM))
+            .render(area, buf);
+    }
+}
@@ -0,0 +1,255 @@
+use std::time::Instant;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local, Utc};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::prelude::*;
+use ratatui::widgets::*;
+use ratatui::DefaultTerminal;
+use crate::core::collection::Collection;
+use crate::core::learning::{format_interval, Difficulty};
+use crate::core::queue::LEARN_AHEAD_MINUTES;
+use crate::core::typed_answer::{check_answer, AnswerCheck};
+use crate::repository::deck::{CardItem, TypedAnswerOption};
+use crate::ui::main_card::MainCard;
+use crate::ui::progress_header::ProgressHeader;
+use crate::ui::typed_answer::TypedAnswer;
+
+const COMMAND_HINT_1: &str = "(q: quit | r: reveal | i: ignore | u: undo)";
+const COMMAND_HINT_2: &str = "(q: quit | a: easy, s: good, d: hard, f: again | i: ignore | u: undo)";
+const COMMAND_HINT_TYPING: &str = "(esc: quit | enter: check the answer)";
+const COMMAND_HINT_CHECKED: &str = "(q: quit | enter: suggested | a: easy, s: good, d: hard, f: again | i: ignore | u: undo)";
+const EMPTY_CARD: &str = "All cards are done! (u: undo | any other key: exit)";
+const WAITING_CARD: &str = "Waiting for the next learning step (u: undo | any other key: exit)";
+
+enum Action {
+    Quit,
+    Reveal,
+    Answer(Difficulty),
+    Ignore,
+    Undo,
+    Type(char),
+    Erase,
+    AcceptSuggestion,
+}
+
+/// What the user typed as answer, checked once the card is revealed.
+struct TypedState {
+    option: TypedAnswerOption,
+    typed: String,
+    check: Option<AnswerCheck>,
+}
+
+struct CardView<'a> {
+    deck_name: &'a str,
+    card: &'a CardItem,
+    is_revealed: bool,
+    typed: Option<&'a TypedState>,
+    /// The interval each answer would schedule, shown once revealed.
+    intervals: &'a [(Difficulty, f32)],
+}
+
+fn read_action(is_revealed: bool, typing: bool) -> Result<Option<Action>> {
+    let Event::Key(KeyEvent { code, modifiers, kind, .. }) = event::read()? else {
+        return Ok(None);
+    };
+    if kind != KeyEventKind::Press {
+        return Ok(None);
+    }
+    if modifiers.contains(KeyModifiers::CONTROL) && code == KeyCode::Char('c') {
+        return Ok(Some(Action::Quit));
+    }
+    if typing && !is_revealed {
+        let action = match code {
+            KeyCode::Esc => Action::Quit,
+            KeyCode::Enter => Action::Reveal,
+            KeyCode::Backspace => Action::Erase,
+            KeyCode::Char(c) if !modifiers.contains(KeyModifiers::CONTROL) => Action::Type(c),
+            _ => return Ok(None),
+        };
+        return Ok(Some(action));
+    }
+    let action = match (code, is_revealed) {
+        (KeyCode::Enter, true) if typing => Action::AcceptSuggestion,
+        (KeyCode::Char('q'), _) => Action::Quit,
+        (KeyCode::Char('i'), _) => Action::Ignore,
+        (KeyCode::Char('u'), _) => Action::Undo,
+        (KeyCode::Char('r'), false) => Action::Reveal,
+        (KeyCode::Char('a'), true) => Action::Answer(Difficulty::Easy),
+        (KeyCode::Char('s'), true) => Action::Answer(Difficulty::Good),
+        (KeyCode::Char('d'), true) => Action::Answer(Difficulty::Hard),
+        (KeyCode::Char('f'), true) => Action::Answer(Difficulty::Again),
+        _ => return Ok(None),
+    };
+    Ok(Some(action))
+}
+
+fn draw(frame: &mut Frame, collection: &Collection, view: Option<&CardView>, message: &str, next_due: Option<DateTime<Utc>>) {
+    let typed_height = if view.is_some_and(|view| view.typed.is_some()) { 2 } else { 0 };
+    let intervals_height = if view.is_some_and(|view| view.is_revealed) { 1 } else { 0 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(
+            [
+                Constraint::Length(2),
+                Constraint::Min(6),
+                Constraint::Length(typed_height),
+                Constraint::Length(intervals_height),
+                Constraint::Length(1),
+                Constraint::Length(1),
+            ]
+        )
+        .split(frame.area());
+    let deck_name = match view {
+        Some(view) if view.deck_name != collection.name() => format!("{} > {}", collection.name(), view.deck_name),
+        _ => collection.name().to_string(),
+    };
+    frame.render_widget(ProgressHeader {
+        deck_name,
+        progress: collection.progress(),
+    }, chunks[0]);
+    let hint = match view {
+        None => {
+            let text = match next_due {
+                Some(due) => vec![
+                    Line::from(WAITING_CARD).bold(),
+                    Line::from(format!("The next card is due at {}", due.with_timezone(&Local).format("%H:%M"))),
+                ],
+                None => vec![Line::from(EMPTY_CARD).bold()],
+            };
+            frame.render_widget(Paragraph::new(text), chunks[1]);
+            ""
+        }
+        Some(view) => {
+            frame.render_widget(MainCard {
+                is_revealed: view.is_revealed,
+                content: view.card.clone(),
+            }, chunks[1]);
+            if view.is_revealed {
+                let intervals: Vec<_> = view.intervals.iter()
+                    .map(|(difficulty, interval)| format!("{}: {}", difficulty.key(), format_interval(*interval)))
+                    .collect();
+                frame.render_widget(Paragraph::new(intervals.join("  ")), chunks[3]);
+            }
+            match (view.typed, view.is_revealed) {
+                (Some(typed), _) => {
+                    frame.render_widget(TypedAnswer {
+                        typed: typed.typed.clone(),
+                        check: typed.check.clone(),
+                    }, chunks[2]);
+                    if view.is_revealed { COMMAND_HINT_CHECKED } else { COMMAND_HINT_TYPING }
+                }
+                (None, true) => COMMAND_HINT_2,
+                (None, false) => COMMAND_HINT_1,
+            }
+        }
+    };
+    frame.render_widget(Paragraph::new(hint).dark_gray(), chunks[4]);
+    frame.render_widget(Paragraph::new(message).italic(), chunks[5]);
+}
+
+async fn review_loop(terminal: &mut DefaultTerminal, collection: &mut Collection, typed: bool) -> Result<()> {
+    let mut message = String::new();
+    let mut undone = None;
+    'cards_loop: loop {
+        let Some(id) = undone.take().or_else(|| collection.next_card()) else {
+            let next_due = collection.next_learning_due();
+            terminal.draw(|frame| draw(frame, collection, None, &message, next_due))?;
+            loop {
+                // the next learning card can be shown once it is within the learn ahead limit
+                if let Some(due) = next_due {
+                    let wait = (due - Duration::minutes(LEARN_AHEAD_MINUTES) - Utc::now()).to_std().unwrap_or_default();
+                    if !event::poll(wait)? {
+                        continue 'cards_loop;
+                    }
+                }
+                let Event::Key(key) = event::read()? else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if key.code != KeyCode::Char('u') {
+                    break 'cards_loop;
+                }
+                if let Some(undone_id) = collection.undo().await? {
+                    message = "Last answer undone".to_string();
+                    undone = Some(undone_id);
+                    continue 'cards_loop;
+                }
+            }
+        };
+        let card = collection.card(id).clone();
+        let deck_name = collection.deck_name(id).to_string();
+        let mut typed_state = collection.typed_answer(id).cloned()
+            .or_else(|| typed.then(TypedAnswerOption::default))
+            .map(|option| TypedState { option, typed: String::new(), check: None });
+        let intervals = collection.preview_intervals(id);
+        let mut is_revealed = false;
+        let shown_at = Instant::now();
+        loop {
+            let view = CardView { deck_name: &deck_name, card: &card, is_revealed, typed: typed_state.as_ref(), intervals: &intervals };
+            terminal.draw(|frame| draw(frame, collection, Some(&view), &message, None))?;
+            match read_action(is_revealed, typed_state.is_some())? {
+                None => continue,
+                Some(Action::Quit) => break 'cards_loop,
+                Some(Action::Reveal) => {
+                    is_revealed = true;
+                    if let Some(ref mut state) = typed_state {
+                        state.check = Some(check_answer(&state.typed, &card.answer(), &state.option));
+                    }
+                }
+                Some(Action::Type(c)) => {
+                    if let Some(ref mut state) = typed_state {
+                        state.typed.push(c);
+                    }
+                }
+                Some(Action::Erase) => {
+                    if let Some(ref mut state) = typed_state {
+                        state.typed.pop();
+                    }
+                }
+                Some(Action::AcceptSuggestion) => {
+                    let Some(difficulty) = typed_state.as_ref().and_then(|state| state.check.as_ref()).map(|check| check.suggested) else {
+                        continue;
+                    };
+                    message = format!("Card marked as {}", difficulty.label());
+                    collection.next_state(id, difficulty, shown_at.elapsed()).await?;
+                    continue 'cards_loop;
+                }
+                Some(Action::Undo) => {
+                    match collection.undo().await? {
+                        Some(undone_id) => {
+                            message = "Last answer undone".to_string();
+                            undone = Some(undone_id);
+                            continue 'cards_loop;
+                        }
+                        None => message = "Nothing to undo".to_string(),
+                    }
+                }
+                Some(Action::Ignore) => {
+                    collection.set_ignored(id).await?;
+                    message = "Card ignored".to_string();
+                    continue 'cards_loop;
+                }
+                Some(Action::Answer(difficulty)) => {
+                    message = format!("Card marked as {}", difficulty.label());
+                    collection.next_state(id, difficulty, shown_at.elapsed()).await?;
+                    continue 'cards_loop;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run a full-screen review session until the user quits or no card is due.
+///
+/// The terminal is restored on return, on error and (through the ratatui panic hook) on panic.
+/// With `typed`, answers are typed before reveal even for decks without a `typed_answer` setting.
+pub async fn run(collection: &mut Collection, typed: bool) -> Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let result = review_loop(&mut terminal, collection, typed).await;
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,42 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::prelude::*;
+use ratatui::widgets::*;
+use crate::core::typed_answer::{AnswerCheck, DiffKind};
+
+pub struct TypedAnswer {
+    pub typed: String,
+    pub check: Option<AnswerCheck>,
+}
+
+impl Widget for TypedAnswer {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let lines = match self.check {
+            None => vec![Line::from(vec![
+                Span::from("> ").dark_gray(),
+                Span::from(self.typed),
+                Span::from("_").slow_blink(),
+            ])],
+            Some(check) => {
+                let mut diff = vec![Span::from("> ").dark_gray()];
+                diff.extend(check.segments.into_iter().map(|segment| match segment.kind {
+                    DiffKind::Same => Span::from(segment.text).green(),
+                    DiffKind::Missing => Span::from(segment.text).red().underlined(),
+                    DiffKind::Extra => Span::from(segment.text).dark_gray().crossed_out(),
+                }));
+                vec![
+                    Line::from(diff),
+                    Line::from(format!(
+                        "{:.0}% match, suggested: {}",
+                        check.similarity * 100.0,
+                        check.suggested.label(),
+                    )).italic(),
+                ]
+            }
+        };
+        Paragraph::new(lines).render(area, buf);
+    }
+}